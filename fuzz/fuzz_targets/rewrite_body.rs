@@ -0,0 +1,52 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use serde_json::Value;
+
+#[path = "../../src/rewrite.rs"]
+mod rewrite;
+
+// Quotes and backslashes check that the credentials are escaped properly.
+const CREDENTIALS: &str = "user:\"pass\\";
+
+fn private_method(method: &str) -> Option<&'static str> {
+    rewrite::PRIVATE_METHODS
+        .iter()
+        .find(|(public, _)| *public == method)
+        .map(|(_, private)| *private)
+}
+
+// Checks a single call of the original request against its rewritten version.
+fn check_call(original: &Value, rewritten: &Value) {
+    let method = original["method"].as_str().unwrap_or_default();
+    match private_method(method) {
+        Some(private) if rewritten != original => {
+            assert_eq!(rewritten["method"], private);
+            let params = rewritten["params"].as_array().unwrap();
+            assert_eq!(params[0], CREDENTIALS);
+            let original_params = original["params"].as_array().cloned().unwrap_or_default();
+            assert_eq!(params[1..], original_params[..]);
+            assert_eq!(rewritten["id"], original["id"]);
+        }
+        // Everything else is passed through as it was.
+        _ => assert_eq!(rewritten, original),
+    }
+}
+
+fuzz_target!(|body: &[u8]| {
+    let Some(rewritten) = rewrite::rewrite_body(body, CREDENTIALS, private_method) else {
+        return;
+    };
+    // Only valid JSON is ever rewritten - and the result must be valid JSON again.
+    let original: Value = serde_json::from_slice(body).unwrap();
+    let rewritten: Value = serde_json::from_slice(&rewritten).unwrap();
+    match (&original, &rewritten) {
+        (Value::Array(original), Value::Array(rewritten)) => {
+            assert_eq!(original.len(), rewritten.len());
+            for (original, rewritten) in original.iter().zip(rewritten) {
+                check_call(original, rewritten);
+            }
+        }
+        (original, rewritten) => check_call(original, rewritten),
+    }
+});
@@ -0,0 +1,388 @@
+// Admin namespace - runtime management of the proxy. It is served on its own address, and
+// every request needs the admin token (`Authorization: Bearer <token>`).
+//
+// Changes of the whitelist and of the maintenance mode are written back to the config file,
+// so they survive a restart (comments in the file are not kept). Credentials only live in
+// memory, so their revocation is not persisted.
+
+use std::{
+    fmt::{self, Display},
+    fs,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use futures::future::BoxFuture;
+use hyper::{header, Body, Request, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tower::{Layer, Service};
+use zksync_types::Address;
+use zksync_web3_decl::jsonrpsee::{
+    core::RpcResult,
+    proc_macros::rpc,
+    types::{error::ErrorCode, ErrorObject, ErrorObjectOwned},
+};
+
+use zksync_web3_decl::*;
+
+use crate::audit::{credential_hash, AuditEvent, AuditLog};
+use crate::builder::DefaultPolicy;
+use crate::cache::ResponseCache;
+use crate::config::WhitelistEntry;
+use crate::metrics;
+use crate::policy::SwappablePolicy;
+use crate::proxy::CredentialStore;
+use crate::pubsub::is_websocket_upgrade;
+use crate::rate_limit::error_response;
+use crate::response_filter::ResponseFilters;
+
+/// Error code for requests rejected while the proxy is in maintenance.
+pub const MAINTENANCE_CODE: i32 = -32003;
+pub const MAINTENANCE_MSG: &str = "proxy is in maintenance";
+
+#[derive(Deserialize, Clone, Default)]
+pub struct AdminConfig {
+    // Address of the admin server, e.g. "127.0.0.1:8016".
+    pub address: Option<String>,
+    // Expected in the 'Authorization: Bearer' header - required if the address is set.
+    pub token: Option<String>,
+}
+
+// The config is logged on startup, so the token is left out.
+impl fmt::Debug for AdminConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AdminConfig")
+            .field("address", &self.address)
+            .field("token", &self.token.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CredentialInfo {
+    // sha256 of the credential (as in the audit log) - credentials themselves are never returned.
+    pub id: String,
+    pub addresses: Vec<Address>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AdminStats {
+    pub uptime_secs: u64,
+    pub maintenance: bool,
+    pub whitelist_entries: usize,
+    pub credentials: usize,
+    pub cache_hits: u64,
+    pub cache_misses: u64,
+}
+
+#[rpc(server, namespace = "admin")]
+pub trait AdminNamespace {
+    #[method(name = "listWhitelist")]
+    fn list_whitelist(&self) -> RpcResult<Vec<WhitelistEntry>>;
+    /// Adds the entry - or replaces the one with the same address, code hash or factory.
+    #[method(name = "addWhitelistEntry")]
+    fn add_whitelist_entry(&self, entry: WhitelistEntry) -> RpcResult<bool>;
+    /// Removes the entry with the given address, code hash or factory.
+    #[method(name = "removeWhitelistEntry")]
+    fn remove_whitelist_entry(&self, key: String) -> RpcResult<bool>;
+    #[method(name = "listCredentials")]
+    fn list_credentials(&self) -> RpcResult<Vec<CredentialInfo>>;
+    /// Revokes the credential with the given id (from listCredentials) and all its addresses.
+    #[method(name = "revokeCredential")]
+    fn revoke_credential(&self, id: String) -> RpcResult<bool>;
+    #[method(name = "stats")]
+    fn stats(&self) -> RpcResult<AdminStats>;
+    #[method(name = "setMaintenance")]
+    fn set_maintenance(&self, enabled: bool) -> RpcResult<bool>;
+}
+
+pub struct Admin {
+    // Also serializes the updates (and the writes of the config file).
+    pub(crate) whitelist: Mutex<Vec<WhitelistEntry>>,
+    // None if the whitelist was replaced by a custom policy.
+    pub(crate) policy: Option<(DefaultPolicy, Arc<SwappablePolicy>)>,
+    pub(crate) response_filters: Arc<ResponseFilters>,
+    pub(crate) credentials: CredentialStore,
+    pub(crate) cache: Arc<ResponseCache>,
+    pub(crate) audit: Arc<AuditLog>,
+    pub(crate) maintenance: Arc<AtomicBool>,
+    // Where the changes are persisted (not at all if None).
+    pub(crate) config_path: Option<String>,
+    pub(crate) started: Instant,
+}
+
+impl Admin {
+    // Applies the update to a copy of the whitelist - it's used only if the new policy
+    // can be built and the config written.
+    fn update_whitelist(
+        &self,
+        update: impl FnOnce(&mut Vec<WhitelistEntry>) -> bool,
+    ) -> RpcResult<bool> {
+        let Some((default_policy, policy)) = &self.policy else {
+            return Err(invalid_params("whitelist is replaced by a custom policy"));
+        };
+        let mut whitelist = self.whitelist.lock().unwrap();
+        let mut updated = whitelist.clone();
+        if !update(&mut updated) {
+            return Ok(false);
+        }
+        let new_policy = default_policy.build(&updated).map_err(invalid_params)?;
+        let response_filters = ResponseFilters::new(&updated).map_err(invalid_params)?;
+        self.persist(&updated, self.maintenance.load(Ordering::Relaxed))?;
+
+        policy.set(new_policy);
+        self.response_filters.replace(response_filters);
+        *whitelist = updated;
+        Ok(true)
+    }
+
+    // Writes the whitelist and the maintenance mode to the config file (other settings are kept).
+    fn persist(&self, whitelist: &[WhitelistEntry], maintenance: bool) -> RpcResult<()> {
+        let Some(path) = &self.config_path else {
+            return Ok(());
+        };
+        let write = || -> eyre::Result<()> {
+            let mut config: serde_yaml::Mapping = serde_yaml::from_str(&fs::read_to_string(path)?)?;
+            config.insert("whitelist".into(), serde_yaml::to_value(whitelist)?);
+            config.insert("maintenance".into(), maintenance.into());
+            // Replaced at once, so that a crash doesn't leave a truncated config behind.
+            let tmp_path = format!("{}.tmp", path);
+            fs::write(&tmp_path, serde_yaml::to_string(&config)?)?;
+            fs::rename(&tmp_path, path)?;
+            Ok(())
+        };
+        write().map_err(|err| {
+            tracing::error!("Unable to write config {}: {}", path, err);
+            ErrorObject::owned(
+                ErrorCode::InternalError.code(),
+                format!("unable to write config: {}", err),
+                None::<()>,
+            )
+        })
+    }
+}
+
+fn invalid_params(err: impl Display) -> ErrorObjectOwned {
+    ErrorObject::owned(ErrorCode::InvalidParams.code(), err.to_string(), None::<()>)
+}
+
+impl AdminNamespaceServer for Admin {
+    fn list_whitelist(&self) -> RpcResult<Vec<WhitelistEntry>> {
+        Ok(self.whitelist.lock().unwrap().clone())
+    }
+
+    fn add_whitelist_entry(&self, entry: WhitelistEntry) -> RpcResult<bool> {
+        entry.validate().map_err(invalid_params)?;
+        self.update_whitelist(|whitelist| {
+            let key = entry.key().map(str::to_lowercase);
+            whitelist.retain(|existing| existing.key().map(str::to_lowercase) != key);
+            whitelist.push(entry);
+            true
+        })
+    }
+
+    fn remove_whitelist_entry(&self, key: String) -> RpcResult<bool> {
+        self.update_whitelist(|whitelist| {
+            let len = whitelist.len();
+            whitelist.retain(|entry| {
+                !entry
+                    .key()
+                    .map_or(false, |existing| existing.eq_ignore_ascii_case(&key))
+            });
+            whitelist.len() != len
+        })
+    }
+
+    fn list_credentials(&self) -> RpcResult<Vec<CredentialInfo>> {
+        let data = self.credentials.lock().unwrap();
+        Ok(data
+            .iter()
+            .map(|(credentials, addresses)| CredentialInfo {
+                id: credential_hash(credentials),
+                addresses: addresses.iter().copied().collect(),
+            })
+            .collect())
+    }
+
+    fn revoke_credential(&self, id: String) -> RpcResult<bool> {
+        let mut data = self.credentials.lock().unwrap();
+        let Some(credentials) = data
+            .keys()
+            .find(|credentials| credential_hash(credentials) == id)
+            .cloned()
+        else {
+            return Ok(false);
+        };
+        let addresses = data.remove(&credentials).unwrap_or_default();
+        metrics::set_active_credentials(data.len());
+        drop(data);
+
+        self.audit.record(
+            AuditEvent {
+                event: "admin_revoke_credential",
+                credentials: &credentials,
+                addresses: addresses.into_iter().collect(),
+                contract: None,
+                selector: None,
+            },
+            &Ok(()),
+        );
+        Ok(true)
+    }
+
+    fn stats(&self) -> RpcResult<AdminStats> {
+        let cache = self.cache.stats();
+        Ok(AdminStats {
+            uptime_secs: self.started.elapsed().as_secs(),
+            maintenance: self.maintenance.load(Ordering::Relaxed),
+            whitelist_entries: self.whitelist.lock().unwrap().len(),
+            credentials: self.credentials.lock().unwrap().len(),
+            cache_hits: cache.hits,
+            cache_misses: cache.misses,
+        })
+    }
+
+    fn set_maintenance(&self, enabled: bool) -> RpcResult<bool> {
+        let whitelist = self.whitelist.lock().unwrap();
+        self.persist(&whitelist, enabled)?;
+        self.maintenance.store(enabled, Ordering::Relaxed);
+        tracing::info!("Maintenance mode {}", if enabled { "on" } else { "off" });
+        Ok(enabled)
+    }
+}
+
+// Middleware of the admin server - rejects requests without the admin token.
+#[derive(Clone)]
+pub struct AdminAuthMiddleware<S> {
+    inner: S,
+    // Only the hash is compared, so that the comparison doesn't leak the token.
+    token_hash: [u8; 32],
+}
+
+impl<S> Service<Request<Body>> for AdminAuthMiddleware<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>> + Send + Clone + 'static,
+    S::Future: Send,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let authorized = req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map_or(false, |token| {
+                Sha256::digest(token.as_bytes())[..] == self.token_hash[..]
+            });
+        if !authorized {
+            return Box::pin(async {
+                Ok(Response::builder()
+                    .status(StatusCode::UNAUTHORIZED)
+                    .body(Body::empty())
+                    .unwrap())
+            });
+        }
+        let mut inner = self.inner.clone();
+        Box::pin(async move { inner.call(req).await })
+    }
+}
+
+pub struct AdminAuthLayer {
+    token_hash: [u8; 32],
+}
+
+impl AdminAuthLayer {
+    pub fn new(token: &str) -> Self {
+        AdminAuthLayer {
+            token_hash: Sha256::digest(token.as_bytes()).into(),
+        }
+    }
+}
+
+impl<S> Layer<S> for AdminAuthLayer {
+    type Service = AdminAuthMiddleware<S>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        AdminAuthMiddleware {
+            inner: service,
+            token_hash: self.token_hash,
+        }
+    }
+}
+
+// Middleware of the proxy - rejects all JSON-RPC requests while in maintenance.
+#[derive(Clone)]
+pub struct MaintenanceMiddleware<S> {
+    inner: S,
+    enabled: Arc<AtomicBool>,
+}
+
+impl<S> Service<Request<Body>> for MaintenanceMiddleware<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>> + Send + Clone + 'static,
+    S::Future: Send,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let mut inner = self.inner.clone();
+        if !self.enabled.load(Ordering::Relaxed) {
+            return Box::pin(async move { inner.call(req).await });
+        }
+        Box::pin(async move {
+            // Calls over open connections are rejected by the websocket RPC middleware.
+            if is_websocket_upgrade(&req) {
+                return Ok(Response::builder()
+                    .status(StatusCode::SERVICE_UNAVAILABLE)
+                    .body(Body::from(MAINTENANCE_MSG))
+                    .unwrap());
+            }
+            let (parts, body) = req.into_parts();
+            let body_bytes = hyper::body::to_bytes(body).await.unwrap_or_default();
+
+            // Requests that are not valid JSON are left for the server to reject.
+            if let Ok(body) = serde_json::from_slice::<Value>(&body_bytes) {
+                return Ok(error_response(&body, MAINTENANCE_CODE, MAINTENANCE_MSG));
+            }
+
+            inner
+                .call(Request::from_parts(parts, Body::from(body_bytes)))
+                .await
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct MaintenanceLayer {
+    pub enabled: Arc<AtomicBool>,
+}
+
+impl<S> Layer<S> for MaintenanceLayer {
+    type Service = MaintenanceMiddleware<S>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        MaintenanceMiddleware {
+            inner: service,
+            enabled: self.enabled.clone(),
+        }
+    }
+}
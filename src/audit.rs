@@ -0,0 +1,324 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::Path,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use eyre::{eyre, Context};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use zksync_types::Address;
+
+use crate::error::Denial;
+
+// 'prev_hash' of the first record in the log.
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Single authorization decision (or credential change) that touched private data.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AuditRecord {
+    // Milliseconds since unix epoch.
+    pub timestamp: u64,
+    pub event: String,
+    // Credentials are never stored - only their sha256.
+    pub credential_hash: String,
+    pub addresses: Vec<Address>,
+    pub contract: Option<Address>,
+    pub selector: Option<String>,
+    pub decision: String,
+    pub reason: Option<String>,
+    pub prev_hash: String,
+}
+
+impl AuditRecord {
+    fn hash(&self) -> String {
+        let serialized = serde_json::to_string(self).unwrap();
+        hex::encode(Sha256::digest(serialized.as_bytes()))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct AuditEntry {
+    #[serde(flatten)]
+    record: AuditRecord,
+    hash: String,
+}
+
+/// What is being audited - `decision` and `reason` are filled in from the result.
+pub struct AuditEvent<'a> {
+    pub event: &'a str,
+    // Normalized (see `proxy::normalize_credentials`), so that the hash matches the admin id.
+    pub credentials: &'a str,
+    pub addresses: Vec<Address>,
+    pub contract: Option<Address>,
+    pub selector: Option<String>,
+}
+
+pub fn credential_hash(credentials: &str) -> String {
+    hex::encode(Sha256::digest(credentials.as_bytes()))
+}
+
+struct AuditWriter {
+    file: File,
+    last_hash: String,
+}
+
+/// Append-only JSONL log, where each record contains the hash of the previous one.
+pub struct AuditLog {
+    writer: Option<Mutex<AuditWriter>>,
+}
+
+impl AuditLog {
+    pub fn disabled() -> Self {
+        AuditLog { writer: None }
+    }
+
+    /// Opens (or creates) the log at `path`, continuing the existing hash chain. An incomplete
+    /// last record (left by a write that was interrupted) is removed.
+    pub fn open(path: &str) -> eyre::Result<Self> {
+        let chain = if Path::new(path).exists() {
+            check_chain(path)?
+        } else {
+            Chain::default()
+        };
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .wrap_err_with(|| format!("Unable to open audit log {}", path))?;
+        if let Some((line_number, offset)) = chain.torn_at {
+            tracing::warn!(
+                "Audit log {} ends with an incomplete record (line {}), removing it",
+                path,
+                line_number
+            );
+            file.set_len(offset)
+                .wrap_err_with(|| format!("Unable to truncate audit log {}", path))?;
+        } else if chain.unterminated {
+            writeln!(file).wrap_err_with(|| format!("Unable to write audit log {}", path))?;
+        }
+        Ok(AuditLog {
+            writer: Some(Mutex::new(AuditWriter {
+                file,
+                last_hash: chain.last_hash.unwrap_or_else(|| GENESIS_HASH.to_string()),
+            })),
+        })
+    }
+
+    pub fn record(&self, event: AuditEvent, result: &Result<(), Denial>) {
+        let writer = match &self.writer {
+            Some(writer) => writer,
+            None => return,
+        };
+        let (decision, reason) = match result {
+            Ok(()) => ("allow", None),
+            Err(denial) => ("deny", Some(denial.reason().to_string())),
+        };
+        let mut writer = writer.lock().unwrap();
+        let record = AuditRecord {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or_default(),
+            event: event.event.to_string(),
+            credential_hash: credential_hash(event.credentials),
+            addresses: event.addresses,
+            contract: event.contract,
+            selector: event.selector,
+            decision: decision.to_string(),
+            reason,
+            prev_hash: writer.last_hash.clone(),
+        };
+        let hash = record.hash();
+        let line = serde_json::to_string(&AuditEntry {
+            record,
+            hash: hash.clone(),
+        })
+        .unwrap();
+        // Failing to write the audit log must not go unnoticed, but shouldn't take the proxy down.
+        match writeln!(writer.file, "{}", line).and_then(|_| writer.file.flush()) {
+            Ok(()) => writer.last_hash = hash,
+            Err(err) => tracing::error!("Failed to write audit record: {}", err),
+        }
+    }
+}
+
+/// Checks the hash chain of the log at `path`. Returns the hash of the last record
+/// (or None if the log is empty).
+///
+/// The chain is not keyed - it catches records that were edited, removed or reordered, but
+/// not a log rewritten (or cut short) together with its hashes.
+pub fn verify(path: &str) -> eyre::Result<Option<String>> {
+    let chain = check_chain(path)?;
+    if let Some((line_number, _)) = chain.torn_at {
+        return Err(eyre!(
+            "Incomplete record at line {} (a write was interrupted) - it's removed when the proxy starts",
+            line_number
+        ));
+    }
+    Ok(chain.last_hash)
+}
+
+#[derive(Default)]
+struct Chain {
+    last_hash: Option<String>,
+    // Line and offset of an incomplete last record.
+    torn_at: Option<(usize, u64)>,
+    // The last record is complete, but without its newline.
+    unterminated: bool,
+}
+
+fn check_chain(path: &str) -> eyre::Result<Chain> {
+    let file = File::open(path).wrap_err_with(|| format!("Unable to open audit log {}", path))?;
+    let mut reader = BufReader::new(file);
+    let mut chain = Chain::default();
+    let mut prev_hash = GENESIS_HASH.to_string();
+    let mut line = String::new();
+    let mut line_number = 0;
+    let mut offset = 0;
+    loop {
+        line.clear();
+        let read = reader.read_line(&mut line)?;
+        if read == 0 {
+            break;
+        }
+        line_number += 1;
+        let line_offset = offset;
+        offset += read as u64;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry: AuditEntry = match serde_json::from_str(&line) {
+            Ok(entry) => entry,
+            // Records are written together with their newline - one without it was cut short.
+            Err(_) if !line.ends_with('\n') => {
+                chain.torn_at = Some((line_number, line_offset));
+                break;
+            }
+            Err(err) => {
+                return Err(err)
+                    .wrap_err_with(|| format!("Malformed audit record at line {}", line_number))
+            }
+        };
+        if entry.record.prev_hash != prev_hash {
+            return Err(eyre!(
+                "Broken chain at line {}: expected prev_hash {}, got {}",
+                line_number,
+                prev_hash,
+                entry.record.prev_hash
+            ));
+        }
+        if entry.record.hash() != entry.hash {
+            return Err(eyre!("Record at line {} was modified", line_number));
+        }
+        prev_hash = entry.hash.clone();
+        chain.last_hash = Some(entry.hash);
+        chain.unterminated = !line.ends_with('\n');
+    }
+    Ok(chain)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::PathBuf};
+
+    use super::*;
+
+    // Log with the given number of records, in its own file.
+    fn log_with(records: usize) -> PathBuf {
+        let path = env::temp_dir().join(format!("audit-{}.jsonl", rand::random::<u64>()));
+        let log = AuditLog::open(path.to_str().unwrap()).unwrap();
+        for index in 0..records {
+            log.record(
+                AuditEvent {
+                    event: "eth_call",
+                    credentials: "abcd",
+                    addresses: vec![Address::from_low_u64_be(index as u64)],
+                    contract: None,
+                    selector: None,
+                },
+                &Ok(()),
+            );
+        }
+        path
+    }
+
+    fn lines(path: &PathBuf) -> Vec<String> {
+        fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    fn write_lines(path: &PathBuf, lines: &[String]) {
+        fs::write(path, lines.join("\n") + "\n").unwrap();
+    }
+
+    #[test]
+    fn valid_chain_verifies() {
+        let path = log_with(3);
+        let last: AuditEntry = serde_json::from_str(&lines(&path)[2]).unwrap();
+        assert_eq!(verify(path.to_str().unwrap()).unwrap(), Some(last.hash));
+    }
+
+    #[test]
+    fn edited_record_is_detected() {
+        let path = log_with(3);
+        let mut lines = lines(&path);
+        lines[1] = lines[1].replace("\"allow\"", "\"deny\"");
+        write_lines(&path, &lines);
+        assert!(verify(path.to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    fn deleted_record_is_detected() {
+        let path = log_with(3);
+        let mut lines = lines(&path);
+        lines.remove(1);
+        write_lines(&path, &lines);
+        assert!(verify(path.to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    fn reordered_records_are_detected() {
+        let path = log_with(3);
+        let mut lines = lines(&path);
+        lines.swap(1, 2);
+        write_lines(&path, &lines);
+        assert!(verify(path.to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    fn open_continues_existing_chain() {
+        let path = log_with(2);
+        let log = AuditLog::open(path.to_str().unwrap()).unwrap();
+        log.record(
+            AuditEvent {
+                event: "privateeth_addCredential",
+                credentials: "abcd",
+                addresses: vec![],
+                contract: None,
+                selector: None,
+            },
+            &Err(Denial::UnknownCredential),
+        );
+        assert_eq!(lines(&path).len(), 3);
+        assert!(verify(path.to_str().unwrap()).unwrap().is_some());
+    }
+
+    #[test]
+    fn open_removes_incomplete_last_record() {
+        let path = log_with(2);
+        let complete = lines(&path);
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        write!(file, "{}", &complete[1][..20]).unwrap();
+        assert!(verify(path.to_str().unwrap()).is_err());
+
+        AuditLog::open(path.to_str().unwrap()).unwrap();
+        assert_eq!(lines(&path), complete);
+        assert!(verify(path.to_str().unwrap()).is_ok());
+    }
+}
@@ -0,0 +1,473 @@
+use std::{
+    collections::HashSet,
+    net::SocketAddr,
+    str::FromStr,
+    sync::{atomic::AtomicBool, Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use eyre::Context;
+use futures::future::BoxFuture;
+use hyper::{Body, Request, Response};
+use tokio::net::{TcpListener, TcpStream};
+use tower::Service;
+use zksync_types::url::SensitiveUrl;
+use zksync_web3_decl::{
+    jsonrpsee::{
+        server::{
+            middleware::rpc::RpcServiceBuilder, stop_channel, ServerBuilder, ServerHandle,
+            StopHandle,
+        },
+        Methods, RpcModule,
+    },
+    namespaces::{EthNamespaceServer, Web3NamespaceServer, ZksNamespaceServer},
+};
+
+use crate::admin::{Admin, AdminAuthLayer, AdminConfig, AdminNamespaceServer, MaintenanceLayer};
+use crate::audit::AuditLog;
+use crate::cache::ResponseCache;
+use crate::config::{Config, WhitelistEntry};
+use crate::filters::FilterRegistry;
+use crate::instances::{InstanceConfig, InstanceLookup};
+use crate::logging::RequestSpanLayer;
+use crate::metrics::{self, MetricsConfig, MetricsLayer};
+use crate::middleware::{get_credentials_from_request, AuthMiddlewareLayer};
+use crate::ownership::{OwnershipConfig, OwnershipPolicy};
+use crate::policy::{AnyOf, Policy, SwappablePolicy};
+use crate::proxy::{
+    self, CredentialStore, NodeInfo, PrivateEthNamespaceServer, PrivateProxy, Proxy,
+};
+use crate::pubsub::{
+    is_websocket_upgrade, EthSubscribeServer, PrivateEthSubscribeServer, Subscriptions,
+    WsAuthLayer, WsCallLayer,
+};
+use crate::rate_limit::{PeerAddr, RateLimitConfig, RateLimitLayer, RateLimiter};
+use crate::response_filter::ResponseFilters;
+use crate::script::{ScriptConfig, ScriptPolicy};
+use crate::whitelist::ContractWhitelist;
+
+/// Builds the proxy (all namespaces with their shared state) from the config.
+pub struct ProxyBuilder {
+    sequencer_url: String,
+    config: Config,
+    policy: Option<Arc<dyn Policy>>,
+    config_path: Option<String>,
+}
+
+impl ProxyBuilder {
+    pub fn new(sequencer_url: impl Into<String>, config: Config) -> Self {
+        ProxyBuilder {
+            sequencer_url: sequencer_url.into(),
+            config,
+            policy: None,
+            config_path: None,
+        }
+    }
+
+    /// File that the changes made through the admin namespace are written to - without it,
+    /// they only last until the proxy restarts.
+    pub fn config_path(mut self, path: impl Into<String>) -> Self {
+        self.config_path = Some(path.into());
+        self
+    }
+
+    /// Replaces the whitelist from the config (and its scripts) with a custom policy, which
+    /// can include the whitelist, e.g. `DenyOverrides(vec![whitelist, custom])`. The admin
+    /// namespace then can't change the whitelist.
+    pub fn policy(mut self, policy: Arc<dyn Policy>) -> Self {
+        self.policy = Some(policy);
+        self
+    }
+
+    /// Creates the RPC module.
+    pub async fn build(self) -> eyre::Result<BuiltProxy> {
+        let config = self.config;
+        let sequencer_url = self.sequencer_url;
+        // Clients are created per request - they would only fail then.
+        SensitiveUrl::from_str(&sequencer_url)
+            .map_err(|err| eyre::eyre!("Invalid sequencer URL: {}", err))?;
+        for entry in &config.whitelist {
+            entry.validate()?;
+        }
+        if config.admin.address.is_some() && config.admin.token.is_none() {
+            eyre::bail!("Admin namespace needs a token ('admin.token')");
+        }
+        let response_filters = Arc::new(ResponseFilters::new(&config.whitelist)?);
+        // The admin namespace can only change the whitelist if it's not replaced by a custom policy.
+        let (policy, whitelist_policy): (Arc<dyn Policy>, _) = match self.policy {
+            Some(policy) => (policy, None),
+            None => {
+                let default_policy = DefaultPolicy {
+                    sequencer_url: sequencer_url.clone(),
+                    scripts: config.scripts.clone(),
+                    ownership: config.ownership.clone(),
+                    instances: config.instances.clone(),
+                };
+                let policy = Arc::new(SwappablePolicy::new(
+                    default_policy.build(&config.whitelist)?,
+                ));
+                (policy.clone(), Some((default_policy, policy)))
+            }
+        };
+
+        let cache = Arc::new(ResponseCache::new(&config.cache));
+
+        let audit = Arc::new(match &config.audit_log {
+            Some(path) => AuditLog::open(path)?,
+            None => AuditLog::disabled(),
+        });
+
+        let filters = Arc::new(FilterRegistry::new(config.filters));
+
+        let proxy = Proxy {
+            sequencer_url: sequencer_url.clone(),
+            policy: policy.clone(),
+            response_filters: response_filters.clone(),
+            estimation: config.estimation.clone(),
+            cache: cache.clone(),
+            filters: filters.clone(),
+            redaction: config.block_redaction.clone(),
+            verbose_errors: config.verbose_errors,
+        };
+
+        let credentials = CredentialStore::default();
+
+        let subscriptions = Subscriptions {
+            sequencer_url: sequencer_url.clone(),
+            policy: policy.clone(),
+            credentials: credentials.clone(),
+            poll_interval: Duration::from_millis(config.subscriptions.poll_interval_ms),
+            redaction: config.block_redaction.clone(),
+            verbose_errors: config.verbose_errors,
+        };
+
+        let private_proxy = PrivateProxy {
+            sequencer_url,
+            policy,
+            response_filters: response_filters.clone(),
+            estimation: config.estimation,
+            verbose_errors: config.verbose_errors,
+            audit: audit.clone(),
+            filters,
+            redaction: config.block_redaction,
+            credentials: credentials.clone(),
+        };
+
+        let node_info = NodeInfo {
+            proxy: proxy.clone(),
+        };
+
+        let mut rpc = RpcModule::new(());
+        rpc.merge(EthNamespaceServer::into_rpc(proxy.clone()))
+            .unwrap();
+        rpc.merge(ZksNamespaceServer::into_rpc(proxy)).unwrap();
+        let private_rpc = private_proxy.into_rpc();
+        proxy::validate_private_methods(private_rpc.method_names())?;
+        rpc.merge(private_rpc).unwrap();
+        rpc.merge(node_info.clone().net_rpc()).unwrap();
+        rpc.merge(Web3NamespaceServer::into_rpc(node_info)).unwrap();
+
+        let mut ws_rpc = RpcModule::new(());
+        ws_rpc
+            .merge(EthSubscribeServer::into_rpc(subscriptions.clone()))
+            .unwrap();
+        ws_rpc
+            .merge(PrivateEthSubscribeServer::into_rpc(subscriptions))
+            .unwrap();
+
+        let maintenance = Arc::new(AtomicBool::new(config.maintenance));
+        let admin = Admin {
+            whitelist: Mutex::new(config.whitelist),
+            policy: whitelist_policy,
+            response_filters,
+            credentials: credentials.clone(),
+            cache: cache.clone(),
+            audit,
+            maintenance: maintenance.clone(),
+            config_path: self.config_path,
+            started: Instant::now(),
+        };
+
+        Ok(BuiltProxy {
+            rpc,
+            ws_rpc,
+            admin: admin.into_rpc(),
+            cache,
+            credentials,
+            maintenance,
+            admin_config: config.admin,
+            rate_limit: config.rate_limit,
+            metrics: config.metrics,
+            verbose_errors: config.verbose_errors,
+        })
+    }
+}
+
+/// Builds the policy from the whitelist - at startup, and again when the whitelist changes.
+#[derive(Clone)]
+pub(crate) struct DefaultPolicy {
+    sequencer_url: String,
+    scripts: ScriptConfig,
+    ownership: OwnershipConfig,
+    instances: InstanceConfig,
+}
+
+impl DefaultPolicy {
+    pub(crate) fn build(&self, whitelist: &[WhitelistEntry]) -> eyre::Result<Arc<dyn Policy>> {
+        // Rules that can allow calls the methods of the whitelist don't.
+        let mut policies: Vec<Arc<dyn Policy>> = vec![];
+        let scripts = ScriptPolicy::load(&self.sequencer_url, whitelist, self.scripts.clone())?;
+        if !scripts.is_empty() {
+            policies.push(Arc::new(scripts));
+        }
+        let ownership = OwnershipPolicy::new(&self.sequencer_url, whitelist, &self.ownership)?;
+        if !ownership.is_empty() {
+            policies.push(Arc::new(ownership));
+        }
+
+        let instances = InstanceLookup::new(&self.sequencer_url, whitelist, &self.instances);
+        let whitelist = Arc::new(
+            ContractWhitelist::init(whitelist.to_vec())?.with_instances(Arc::new(instances)),
+        );
+        if policies.is_empty() {
+            Ok(whitelist)
+        } else {
+            // They decide first - calls they don't allow still go through the whitelist. Entries
+            // with a script have no methods, so the whitelist can't allow what a script denies.
+            policies.push(whitelist);
+            Ok(Arc::new(AnyOf(policies)))
+        }
+    }
+}
+
+/// The proxy, ready to be served - either with `start`, or mounted on an existing server by
+/// merging `rpc` into its module (its http middleware then needs `AuthMiddlewareLayer`,
+/// so that requests with credentials reach the private namespace).
+pub struct BuiltProxy {
+    pub rpc: RpcModule<()>,
+    // Subscriptions - served only over websockets (together with `rpc`).
+    pub ws_rpc: RpcModule<()>,
+    // Admin namespace - `start` serves it on its own address (if configured).
+    pub admin: RpcModule<()>,
+    pub cache: Arc<ResponseCache>,
+    // Credentials added through privateeth_addCredential.
+    pub credentials: CredentialStore,
+    // Set through admin_setMaintenance.
+    pub maintenance: Arc<AtomicBool>,
+    admin_config: AdminConfig,
+    rate_limit: RateLimitConfig,
+    metrics: MetricsConfig,
+    verbose_errors: bool,
+}
+
+impl BuiltProxy {
+    /// Starts the server (HTTP and websockets) with all the middleware on `address`.
+    pub async fn start(self, address: &str) -> eyre::Result<RunningProxy> {
+        let listener = TcpListener::bind(address)
+            .await
+            .wrap_err_with(|| format!("Unable to listen on {}", address))?;
+        let local_addr = listener.local_addr()?;
+
+        // Metrics tell how the proxy is used, so they are only served on their own address.
+        let metrics_listener = match &self.metrics.address {
+            Some(metrics_address) => Some(
+                TcpListener::bind(metrics_address)
+                    .await
+                    .wrap_err_with(|| format!("Unable to listen on {}", metrics_address))?,
+            ),
+            None => None,
+        };
+        let metrics_addr = match &metrics_listener {
+            Some(listener) => Some(listener.local_addr()?),
+            None => None,
+        };
+
+        let cors_layer = tower_http::cors::CorsLayer::very_permissive();
+
+        let limiter = Arc::new(RateLimiter::new(self.rate_limit));
+        let rate_limit_layer = RateLimitLayer {
+            limiter: limiter.clone(),
+        };
+
+        let known_methods: Arc<HashSet<String>> = Arc::new(
+            self.rpc
+                .method_names()
+                .chain(self.ws_rpc.method_names())
+                .map(|name| name.to_string())
+                .collect(),
+        );
+        let metrics_layer = MetricsLayer {
+            known_methods: known_methods.clone(),
+        };
+
+        // Metrics and rate limiting go first, so that they see the original (not rewritten) method names.
+        // For websockets, they only see the handshake - calls are checked by WsCallLayer.
+        let http_middleware = tower::ServiceBuilder::new()
+            .layer(RequestSpanLayer)
+            .layer(metrics_layer)
+            .layer(MaintenanceLayer {
+                enabled: self.maintenance.clone(),
+            })
+            .layer(rate_limit_layer)
+            .layer(WsAuthLayer {
+                credentials: self.credentials,
+            })
+            .layer(AuthMiddlewareLayer {})
+            .layer(cors_layer);
+        let service_builder = ServerBuilder::default()
+            .set_http_middleware(http_middleware)
+            .to_service_builder();
+
+        let ws_call_layer = WsCallLayer {
+            limiter,
+            maintenance: self.maintenance,
+            known_methods,
+            verbose_errors: self.verbose_errors,
+            client_ip: None,
+            credentials: None,
+        };
+        let methods = Methods::from(self.rpc);
+        let mut ws_methods = methods.clone();
+        ws_methods.merge(self.ws_rpc)?;
+
+        // jsonrpsee's own server runs the calls made over websockets without the http
+        // middleware (and doesn't tell it the peer address), so connections are accepted here
+        // and every websocket connection gets its own RPC middleware (with the client and
+        // credential of its handshake).
+        let (stop_handle, handle) = stop_channel();
+        let connection_stop = stop_handle.clone();
+        tokio::spawn(accept_connections(
+            listener,
+            stop_handle.clone(),
+            move |peer_addr| {
+                let service_builder = service_builder.clone();
+                let methods = methods.clone();
+                let ws_methods = ws_methods.clone();
+                let ws_call_layer = ws_call_layer.clone();
+                let connection_stop = connection_stop.clone();
+                hyper::service::service_fn(move |mut req: Request<Body>| -> ResponseFuture {
+                    req.extensions_mut().insert(PeerAddr(peer_addr));
+                    if is_websocket_upgrade(&req) {
+                        let ws_call_layer = WsCallLayer {
+                            client_ip: ws_call_layer.limiter.client_ip(&req),
+                            credentials: get_credentials_from_request(&req),
+                            ..ws_call_layer.clone()
+                        };
+                        service_builder
+                            .clone()
+                            .set_rpc_middleware(RpcServiceBuilder::new().layer(ws_call_layer))
+                            .build(ws_methods.clone(), connection_stop.clone())
+                            .call(req)
+                    } else {
+                        service_builder
+                            .clone()
+                            .build(methods.clone(), connection_stop.clone())
+                            .call(req)
+                    }
+                })
+            },
+        ));
+
+        if let Some(listener) = metrics_listener {
+            tokio::spawn(accept_connections(listener, stop_handle, |_| {
+                hyper::service::service_fn(metrics::serve)
+            }));
+        }
+
+        let (admin_handle, admin_addr) = match self.admin_config {
+            AdminConfig {
+                address: Some(admin_address),
+                token: Some(token),
+            } => {
+                let admin_server = ServerBuilder::default()
+                    .http_only()
+                    .set_http_middleware(
+                        tower::ServiceBuilder::new()
+                            .layer(RequestSpanLayer)
+                            .layer(AdminAuthLayer::new(&token)),
+                    )
+                    .build(&admin_address)
+                    .await
+                    .wrap_err_with(|| format!("Unable to listen on {}", admin_address))?;
+                let admin_addr = admin_server.local_addr()?;
+                (Some(admin_server.start(self.admin)), Some(admin_addr))
+            }
+            _ => (None, None),
+        };
+
+        Ok(RunningProxy {
+            handle,
+            local_addr,
+            admin_handle,
+            admin_addr,
+            metrics_addr,
+            cache: self.cache,
+        })
+    }
+}
+
+type BoxError = Box<dyn std::error::Error + Send + Sync + 'static>;
+type ResponseFuture = BoxFuture<'static, Result<Response<Body>, BoxError>>;
+
+// Accepts connections until the server stops - `make_service` creates the service for each
+// one (given the address of its peer).
+async fn accept_connections<F, S>(listener: TcpListener, stop_handle: StopHandle, make_service: F)
+where
+    F: Fn(SocketAddr) -> S,
+    S: Service<Request<Body>, Response = Response<Body>> + Send + 'static,
+    S::Error: Into<BoxError>,
+    S::Future: Send,
+{
+    let stopped = stop_handle.clone().shutdown();
+    tokio::pin!(stopped);
+    loop {
+        let (socket, peer_addr) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(err) => {
+                    tracing::warn!("Unable to accept connection: {}", err);
+                    continue;
+                }
+            },
+            _ = &mut stopped => break,
+        };
+        tokio::spawn(serve_connection(
+            socket,
+            make_service(peer_addr),
+            stop_handle.clone(),
+        ));
+    }
+}
+
+// Serves one connection (with upgrades, for websockets) until it closes or the server stops.
+async fn serve_connection<S>(socket: TcpStream, service: S, stop_handle: StopHandle)
+where
+    S: Service<Request<Body>, Response = Response<Body>> + Send + 'static,
+    S::Error: Into<BoxError>,
+    S::Future: Send,
+{
+    let connection = hyper::server::conn::Http::new()
+        .serve_connection(socket, service)
+        .with_upgrades();
+    tokio::pin!(connection);
+    tokio::select! {
+        result = connection.as_mut() => {
+            if let Err(err) = result {
+                tracing::debug!("Connection failed: {}", err);
+            }
+        }
+        _ = stop_handle.shutdown() => {
+            connection.as_mut().graceful_shutdown();
+            let _ = connection.await;
+        }
+    }
+}
+
+pub struct RunningProxy {
+    pub handle: ServerHandle,
+    pub local_addr: SocketAddr,
+    pub admin_handle: Option<ServerHandle>,
+    pub admin_addr: Option<SocketAddr>,
+    pub metrics_addr: Option<SocketAddr>,
+    pub cache: Arc<ResponseCache>,
+}
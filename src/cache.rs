@@ -0,0 +1,217 @@
+use std::{
+    future::Future,
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use lru::LruCache;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use zksync_types::api::{BlockIdVariant, BlockNumber, BlockNumberObject};
+use zksync_web3_decl::jsonrpsee::core::RpcResult;
+
+use crate::metrics;
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct CacheConfig {
+    // Maximum number of responses kept in memory (0 disables the cache).
+    #[serde(default = "CacheConfig::default_max_entries")]
+    pub max_entries: usize,
+    // How long to keep responses for 'latest'-like block tags (0 - don't cache them at all).
+    #[serde(default)]
+    pub latest_ttl_ms: u64,
+}
+
+impl CacheConfig {
+    fn default_max_entries() -> usize {
+        10_000
+    }
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            max_entries: CacheConfig::default_max_entries(),
+            latest_ttl_ms: 0,
+        }
+    }
+}
+
+/// How long a given response can be cached for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CachePolicy {
+    /// Response can never change (e.g. state at a block hash) - kept until evicted by LRU.
+    Forever,
+    /// Blocks and receipts - their L1 batch fields are null until the batch is sealed, so they
+    /// are kept forever only once 'l1BatchNumber' is set (and like 'latest' until then).
+    UntilSealed,
+    /// Response might change - keep it only for the given time.
+    Ttl(Duration),
+    /// Always go to upstream.
+    NoCache,
+}
+
+struct CacheEntry {
+    value: Value,
+    expires_at: Option<Instant>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl CacheStats {
+    pub fn hit_ratio(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f64 / total as f64
+        }
+    }
+}
+
+/// In-process LRU cache for upstream responses that don't change over time.
+///
+/// Responses are stored as JSON, keyed by method name and serialized params. `null` results
+/// (unknown block, not yet mined receipt etc.) are never cached.
+pub struct ResponseCache {
+    entries: Option<Mutex<LruCache<String, CacheEntry>>>,
+    latest_ttl: Duration,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl ResponseCache {
+    pub fn new(config: &CacheConfig) -> Self {
+        ResponseCache {
+            entries: NonZeroUsize::new(config.max_entries)
+                .map(|size| Mutex::new(LruCache::new(size))),
+            latest_ttl: Duration::from_millis(config.latest_ttl_ms),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Policy for data at a given block - numbered blocks and hashes are immutable,
+    /// while tags like 'latest' move with the chain.
+    pub fn block_policy(&self, block: &Option<BlockIdVariant>) -> CachePolicy {
+        match block {
+            Some(BlockIdVariant::BlockHashObject(_)) => CachePolicy::Forever,
+            Some(BlockIdVariant::BlockNumber(number))
+            | Some(BlockIdVariant::BlockNumberObject(BlockNumberObject {
+                block_number: number,
+            })) => self.block_number_policy(number),
+            // Defaults to 'latest'.
+            None => self.latest_policy(),
+        }
+    }
+
+    pub fn block_number_policy(&self, number: &BlockNumber) -> CachePolicy {
+        match number {
+            BlockNumber::Number(_) | BlockNumber::Earliest => CachePolicy::Forever,
+            _ => self.latest_policy(),
+        }
+    }
+
+    /// Policy for a block itself (see `CachePolicy::UntilSealed`).
+    pub fn block_data_policy(&self, number: &BlockNumber) -> CachePolicy {
+        match self.block_number_policy(number) {
+            CachePolicy::Forever => CachePolicy::UntilSealed,
+            policy => policy,
+        }
+    }
+
+    fn latest_policy(&self) -> CachePolicy {
+        if self.latest_ttl.is_zero() {
+            CachePolicy::NoCache
+        } else {
+            CachePolicy::Ttl(self.latest_ttl)
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<Value> {
+        let mut entries = self.entries.as_ref()?.lock().unwrap();
+        let expired = match entries.get(key) {
+            Some(entry) => match entry.expires_at {
+                Some(expires_at) if expires_at <= Instant::now() => true,
+                _ => return Some(entry.value.clone()),
+            },
+            None => return None,
+        };
+        if expired {
+            entries.pop(key);
+        }
+        None
+    }
+
+    fn put(&self, key: String, value: Value, policy: CachePolicy) {
+        let policy = match policy {
+            CachePolicy::UntilSealed if value["l1BatchNumber"].is_null() => self.latest_policy(),
+            CachePolicy::UntilSealed => CachePolicy::Forever,
+            policy => policy,
+        };
+        let expires_at = match policy {
+            CachePolicy::Forever | CachePolicy::UntilSealed => None,
+            CachePolicy::Ttl(ttl) => Some(Instant::now() + ttl),
+            CachePolicy::NoCache => return,
+        };
+        if let Some(entries) = &self.entries {
+            entries
+                .lock()
+                .unwrap()
+                .put(key, CacheEntry { value, expires_at });
+        }
+    }
+
+    /// Returns the cached response for `method` with `params`, or calls `fetch` and caches
+    /// its result according to `policy`.
+    pub async fn get_or_fetch<T, F, Fut>(
+        &self,
+        method: &str,
+        params: Value,
+        policy: CachePolicy,
+        fetch: F,
+    ) -> RpcResult<T>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = RpcResult<T>>,
+    {
+        if policy == CachePolicy::NoCache || self.entries.is_none() {
+            return fetch().await;
+        }
+        let key = format!("{}:{}", method, params);
+
+        if let Some(value) = self.get(&key) {
+            if let Ok(result) = serde_json::from_value(value) {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                metrics::record_cache_lookup(method, true);
+                return Ok(result);
+            }
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        metrics::record_cache_lookup(method, false);
+
+        let result = fetch().await?;
+        if let Ok(value) = serde_json::to_value(&result) {
+            if !value.is_null() {
+                self.put(key, value, policy);
+            }
+        }
+        Ok(result)
+    }
+}
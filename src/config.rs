@@ -0,0 +1,219 @@
+use std::{fs, str::FromStr};
+
+use eyre::Context;
+use serde::{Deserialize, Serialize};
+use zksync_types::{Address, H256};
+
+use crate::admin::AdminConfig;
+use crate::cache::CacheConfig;
+use crate::estimation::EstimationConfig;
+use crate::filters::FilterConfig;
+use crate::instances::InstanceConfig;
+use crate::metrics::MetricsConfig;
+use crate::ownership::OwnershipConfig;
+use crate::pubsub::SubscriptionConfig;
+use crate::rate_limit::RateLimitConfig;
+use crate::redaction::BlockRedaction;
+use crate::response_filter::ResponseFilter;
+use crate::script::ScriptConfig;
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct WhitelistEntry {
+    // Exactly one of 'address', 'code_hash' (versioned zkSync bytecode hash) and 'factory'.
+    // The last two match every such contract (see instances.rs).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code_hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub factory: Option<String>,
+    pub fully_whitelisted: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub methods: Option<Methods>,
+    // Path to a Rhai script that decides the calls to the contract (see script.rs).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub script: Option<String>,
+}
+
+impl WhitelistEntry {
+    /// Address, code hash or factory - whichever the entry is matched by.
+    pub fn key(&self) -> Option<&str> {
+        self.address
+            .as_deref()
+            .or(self.code_hash.as_deref())
+            .or(self.factory.as_deref())
+    }
+
+    /// Whether the selector is one of the unrestricted or authorized methods.
+    pub fn lists_selector(&self, selector: &str) -> bool {
+        self.methods.as_ref().map_or(false, |methods| {
+            [&methods.unrestricted, &methods.requires_authorization]
+                .into_iter()
+                .flatten()
+                .any(|selectors| selectors.iter().any(|listed| listed == selector))
+        })
+    }
+
+    pub fn validate(&self) -> eyre::Result<()> {
+        let keys = [&self.address, &self.code_hash, &self.factory];
+        if keys.iter().filter(|key| key.is_some()).count() != 1 {
+            eyre::bail!("Whitelist entry needs exactly one of address, code_hash and factory");
+        }
+        if let Some(address) = &self.address {
+            Address::from_str(address).map_err(|_| eyre::eyre!("Invalid address {}", address))?;
+        }
+        if let Some(code_hash) = &self.code_hash {
+            H256::from_str(code_hash)
+                .map_err(|_| eyre::eyre!("Invalid code hash {}", code_hash))?;
+        }
+        if let Some(factory) = &self.factory {
+            Address::from_str(factory).map_err(|_| eyre::eyre!("Invalid factory {}", factory))?;
+        }
+        let owner_lookups = self
+            .methods
+            .as_ref()
+            .and_then(|methods| methods.owner_lookup.as_deref())
+            .unwrap_or_default();
+        for rule in owner_lookups {
+            for selector in [&rule.selector, &rule.lookup] {
+                let mut bytes = [0; 4];
+                hex::decode_to_slice(selector, &mut bytes)
+                    .map_err(|_| eyre::eyre!("Invalid selector {}", selector))?;
+            }
+            // Offset of the argument in the calldata.
+            if rule
+                .arg
+                .checked_mul(32)
+                .and_then(|offset| offset.checked_add(36))
+                .is_none()
+            {
+                eyre::bail!(
+                    "Invalid argument {} of owner lookup {}",
+                    rule.arg,
+                    rule.selector
+                );
+            }
+        }
+        // Scripts run before the whitelist, which would allow what they deny.
+        if self.script.is_some() && (self.fully_whitelisted || self.methods.is_some()) {
+            eyre::bail!(
+                "Whitelist entries with a script can't be fully whitelisted or have methods"
+            );
+        }
+        if self.address.is_some() {
+            return Ok(());
+        }
+        // These are keyed by the contract address.
+        let methods = self.methods.as_ref();
+        if self.script.is_some()
+            || methods.map_or(false, |methods| {
+                methods.owner_lookup.is_some() || methods.response_filters.is_some()
+            })
+        {
+            eyre::bail!(
+                "Whitelist entries matched by code_hash or factory can't have scripts, owner lookups or response filters"
+            );
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Methods {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unrestricted: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub requires_authorization: Option<Vec<String>>,
+    // Methods allowed only to the owner, as returned by a lookup (see ownership.rs).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner_lookup: Option<Vec<OwnerLookup>>,
+    // Results of these methods only contain the caller's records (see response_filter.rs).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_filters: Option<Vec<ResponseFilter>>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct OwnerLookup {
+    // Selector of the guarded method (e.g. tokenURI(uint256)).
+    pub selector: String,
+    // Selector of the lookup that returns the owner (e.g. ownerOf(uint256)).
+    pub lookup: String,
+    // Which argument of the method (counting from 0) is passed to the lookup.
+    #[serde(default)]
+    pub arg: usize,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Config {
+    // If enabled, allows anyone to create new contracts.
+    pub allow_contract_creation: bool,
+
+    // List of contracts that can be called with 'call'.
+    pub whitelist: Vec<WhitelistEntry>,
+
+    // If enabled, denied requests return the reason (contract, selector, rule) in the error.
+    // Useful in dev - keep disabled in production.
+    #[serde(default)]
+    pub verbose_errors: bool,
+
+    // Settings for caching of immutable upstream responses.
+    #[serde(default)]
+    pub cache: CacheConfig,
+
+    // Request budgets per client IP and per credential.
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+
+    // Which block fields (bloom, gas used, size, transaction counts) are hidden.
+    #[serde(default)]
+    pub block_redaction: BlockRedaction,
+
+    // Settings for filters installed with eth_newFilter / eth_newBlockFilter.
+    #[serde(default)]
+    pub filters: FilterConfig,
+
+    // Settings for websocket subscriptions (eth_subscribe).
+    #[serde(default)]
+    pub subscriptions: SubscriptionConfig,
+
+    // Cache of the ownership lookups.
+    #[serde(default)]
+    pub ownership: OwnershipConfig,
+
+    // Limits of the whitelist scripts.
+    #[serde(default)]
+    pub scripts: ScriptConfig,
+
+    // Which estimations (eth_estimateGas, zks_estimateFee) are served without credentials.
+    #[serde(default)]
+    pub estimation: EstimationConfig,
+
+    // Cache of the bytecode hash / factory lookups of contracts that are not listed by address.
+    #[serde(default)]
+    pub instances: InstanceConfig,
+
+    // Admin namespace (on its own address) - disabled if not set.
+    #[serde(default)]
+    pub admin: AdminConfig,
+
+    // Prometheus metrics (on their own address) - not served if not set.
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+
+    // Requests are rejected while in maintenance (can be toggled through the admin namespace).
+    #[serde(default)]
+    pub maintenance: bool,
+
+    // Path to the append-only audit log of authorization decisions (disabled if not set).
+    pub audit_log: Option<String>,
+}
+
+pub fn parse_config(path: &str) -> eyre::Result<Config> {
+    let file_content =
+        fs::read_to_string(path).wrap_err_with(|| format!("Unable to read config {}", path))?;
+
+    // Parse the YAML string into the Config struct
+    let config: Config = serde_yaml::from_str(&file_content)?;
+
+    Ok(config)
+}
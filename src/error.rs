@@ -0,0 +1,223 @@
+use serde_json::{json, Value};
+use zksync_types::Address;
+use zksync_web3_decl::jsonrpsee::{
+    core::ClientError,
+    types::{error::ErrorCode, ErrorObject, ErrorObjectOwned},
+};
+
+/// Returned when the sequencer could not be reached (or answered with garbage),
+/// as opposed to the sequencer returning a proper JSON-RPC error.
+pub const UPSTREAM_UNAVAILABLE_CODE: i32 = -32050;
+pub const UPSTREAM_UNAVAILABLE_MSG: &str = "upstream unavailable";
+
+/// Maps an error from the sequencer client into the error returned to our caller.
+///
+/// JSON-RPC errors are passed through with their code and message, but the `data` field is
+/// dropped - it can contain revert data, which is only safe to return for calls that
+/// already passed the whitelist (see `upstream_call_error`).
+pub fn upstream_error(err: ClientError) -> ErrorObjectOwned {
+    map_client_error(err, false)
+}
+
+/// Like `upstream_error`, but keeps the `data` field (revert reason / custom error payload).
+///
+/// Only use this for requests that were explicitly allowed by the whitelist.
+pub fn upstream_call_error(err: ClientError) -> ErrorObjectOwned {
+    map_client_error(err, true)
+}
+
+fn map_client_error(err: ClientError, include_data: bool) -> ErrorObjectOwned {
+    match err {
+        ClientError::Call(err) => {
+            if include_data {
+                err
+            } else {
+                ErrorObject::owned(err.code(), err.message().to_string(), None::<()>)
+            }
+        }
+        ClientError::Transport(_) | ClientError::RestartNeeded(_) | ClientError::RequestTimeout => {
+            tracing::warn!("Upstream request failed: {}", err);
+            ErrorObject::owned(
+                UPSTREAM_UNAVAILABLE_CODE,
+                UPSTREAM_UNAVAILABLE_MSG,
+                None::<()>,
+            )
+        }
+        _ => {
+            tracing::warn!("Unexpected upstream error: {}", err);
+            ErrorObject::from(ErrorCode::InternalError).into_owned()
+        }
+    }
+}
+
+/// Error code used for every request that was denied by the privacy rules.
+pub const FORBIDDEN_CODE: i32 = 403;
+pub const FORBIDDEN_MSG: &str = "forbidden";
+
+/// Reason why a request was denied.
+///
+/// `reason()` returns a stable identifier that clients can match on. The other fields are only
+/// returned when verbose errors are enabled in the config (useful in dev, terse in production).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Denial {
+    /// Calls without `to` (contract creation) are never allowed through `call`.
+    ContractCreation,
+    /// The target contract is not in the whitelist.
+    ContractNotWhitelisted { contract: Address },
+    /// The contract is whitelisted, but the calldata has no selector.
+    MissingSelector { contract: Address },
+    /// The selector is not listed for this contract (or requires authorization).
+    SelectorNotAllowed { contract: Address, selector: String },
+    /// The request required a credential, but none was provided.
+    MissingCredential,
+    /// The credential is not known to the proxy.
+    UnknownCredential,
+    /// The credential is known, but not bound to this address.
+    AddressNotAuthorized { address: Address },
+    /// The whole RPC method is disabled by the proxy.
+    MethodNotAllowed { method: &'static str },
+    /// Blocks with full transactions are only served with a credential (and only its own ones).
+    FullTransactionsNotAllowed,
+    /// None of the configured policies had an opinion on the request.
+    NotAllowedByPolicy,
+    /// The contract's script denied the call (with its own reason).
+    ScriptDenied { contract: Address, reason: String },
+    /// The contract's script failed or ran out of time.
+    ScriptFailed { contract: Address },
+    /// The ownership lookup didn't return one of the credential's addresses.
+    NotTokenOwner { contract: Address },
+    /// The ownership lookup call failed.
+    OwnerLookupFailed { contract: Address },
+    /// The call result doesn't match the outputs declared for its response filter.
+    ResponseFilterFailed { contract: Address },
+    /// Estimations from this sender need a credential bound to it.
+    SenderRequiresCredential { address: Address },
+    /// The bytecode hash or the deployer of the contract couldn't be fetched.
+    InstanceLookupFailed { contract: Address },
+}
+
+impl Denial {
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Denial::ContractCreation => "contract_creation",
+            Denial::ContractNotWhitelisted { .. } => "contract_not_whitelisted",
+            Denial::MissingSelector { .. } => "missing_selector",
+            Denial::SelectorNotAllowed { .. } => "selector_not_allowed",
+            Denial::MissingCredential => "missing_credential",
+            Denial::UnknownCredential => "unknown_credential",
+            Denial::AddressNotAuthorized { .. } => "address_not_authorized",
+            Denial::MethodNotAllowed { .. } => "method_not_allowed",
+            Denial::FullTransactionsNotAllowed => "full_transactions_not_allowed",
+            Denial::NotAllowedByPolicy => "not_allowed_by_policy",
+            Denial::ScriptDenied { .. } => "script_denied",
+            Denial::ScriptFailed { .. } => "script_failed",
+            Denial::NotTokenOwner { .. } => "not_token_owner",
+            Denial::OwnerLookupFailed { .. } => "owner_lookup_failed",
+            Denial::ResponseFilterFailed { .. } => "response_filter_failed",
+            Denial::SenderRequiresCredential { .. } => "sender_requires_credential",
+            Denial::InstanceLookupFailed { .. } => "instance_lookup_failed",
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            Denial::ContractCreation => "contract creation is not allowed".to_string(),
+            Denial::ContractNotWhitelisted { contract } => {
+                format!("contract {:?} is not whitelisted", contract)
+            }
+            Denial::MissingSelector { contract } => {
+                format!("call to contract {:?} has no method selector", contract)
+            }
+            Denial::SelectorNotAllowed { contract, selector } => format!(
+                "method {} is not allowed on contract {:?}",
+                selector, contract
+            ),
+            Denial::MissingCredential => "credential is required".to_string(),
+            Denial::UnknownCredential => "credential is not known".to_string(),
+            Denial::AddressNotAuthorized { address } => {
+                format!("credential is not authorized for address {:?}", address)
+            }
+            Denial::MethodNotAllowed { method } => format!("method {} is not allowed", method),
+            Denial::FullTransactionsNotAllowed => {
+                "blocks with full transactions require a credential".to_string()
+            }
+            Denial::NotAllowedByPolicy => "no policy allows this request".to_string(),
+            Denial::ScriptDenied { contract, reason } => {
+                format!(
+                    "call to contract {:?} denied by its script: {}",
+                    contract, reason
+                )
+            }
+            Denial::ScriptFailed { contract } => {
+                format!("script of contract {:?} failed", contract)
+            }
+            Denial::NotTokenOwner { contract } => {
+                format!(
+                    "credential does not own this token of contract {:?}",
+                    contract
+                )
+            }
+            Denial::OwnerLookupFailed { contract } => {
+                format!("ownership lookup on contract {:?} failed", contract)
+            }
+            Denial::ResponseFilterFailed { contract } => format!(
+                "result of the call to contract {:?} doesn't match its declared outputs",
+                contract
+            ),
+            Denial::SenderRequiresCredential { address } => {
+                format!("estimation from {:?} requires a credential", address)
+            }
+            Denial::InstanceLookupFailed { contract } => {
+                format!("lookup of contract {:?} failed", contract)
+            }
+        }
+    }
+
+    fn data(&self) -> Value {
+        let mut data = json!({ "reason": self.reason() });
+        match self {
+            Denial::ContractNotWhitelisted { contract }
+            | Denial::MissingSelector { contract }
+            | Denial::ScriptFailed { contract }
+            | Denial::NotTokenOwner { contract }
+            | Denial::OwnerLookupFailed { contract }
+            | Denial::ResponseFilterFailed { contract }
+            | Denial::InstanceLookupFailed { contract } => {
+                data["contract"] = json!(contract);
+            }
+            Denial::ScriptDenied { contract, reason } => {
+                data["contract"] = json!(contract);
+                data["script_reason"] = json!(reason);
+            }
+            Denial::SelectorNotAllowed { contract, selector } => {
+                data["contract"] = json!(contract);
+                data["selector"] = json!(selector);
+            }
+            Denial::AddressNotAuthorized { address }
+            | Denial::SenderRequiresCredential { address } => {
+                data["address"] = json!(address);
+            }
+            Denial::MethodNotAllowed { method } => {
+                data["method"] = json!(method);
+            }
+            Denial::ContractCreation
+            | Denial::MissingCredential
+            | Denial::UnknownCredential
+            | Denial::FullTransactionsNotAllowed
+            | Denial::NotAllowedByPolicy => {}
+        }
+        data
+    }
+
+    /// Converts the denial into the error returned to the client.
+    ///
+    /// In terse mode only the code and a generic message are returned, so that the error
+    /// doesn't reveal e.g. whether a credential exists.
+    pub fn into_error(self, verbose: bool) -> ErrorObjectOwned {
+        if verbose {
+            ErrorObject::owned(FORBIDDEN_CODE, self.message(), Some(self.data()))
+        } else {
+            ErrorObject::owned(FORBIDDEN_CODE, FORBIDDEN_MSG, None::<()>)
+        }
+    }
+}
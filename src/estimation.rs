@@ -0,0 +1,99 @@
+// Gas and fee estimation leaks more than the call itself: estimating a transfer from someone
+// else's account tells whether they can afford it, and the revert reasons tell which branch
+// the contract took. So estimations only run from the caller's own addresses.
+
+use std::collections::HashSet;
+
+use serde::Deserialize;
+use zksync_types::{transaction_request::CallRequest, Address};
+use zksync_web3_decl::jsonrpsee::{
+    core::ClientError,
+    types::{ErrorObject, ErrorObjectOwned},
+};
+
+use crate::error::{upstream_call_error, Denial};
+
+/// Which estimations are served without credentials.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PublicEstimation {
+    // Not at all.
+    Disabled,
+    // Only without a sender (or from the zero address).
+    #[default]
+    Anonymous,
+    // From any sender - like eth_call, only the whitelist applies.
+    AnySender,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct EstimationConfig {
+    #[serde(default)]
+    pub public: PublicEstimation,
+    // Estimations without credentials fail with a generic message, without the revert reason.
+    #[serde(default = "EstimationConfig::default_scrub_reverts")]
+    pub scrub_reverts: bool,
+}
+
+impl EstimationConfig {
+    fn default_scrub_reverts() -> bool {
+        true
+    }
+
+    /// Checks the sender of an estimation without credentials (`method` is the public name).
+    pub fn allow_public(&self, method: &'static str, req: &CallRequest) -> Result<(), Denial> {
+        match (self.public, req.from) {
+            (PublicEstimation::Disabled, _) => Err(Denial::MethodNotAllowed { method }),
+            (PublicEstimation::Anonymous, Some(from)) if !from.is_zero() => {
+                Err(Denial::SenderRequiresCredential { address: from })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Estimations with credentials are only allowed from their own addresses
+    /// (or without a sender).
+    pub fn allow_authorized(
+        &self,
+        req: &CallRequest,
+        users: &HashSet<Address>,
+    ) -> Result<(), Denial> {
+        match req.from {
+            Some(from) if !from.is_zero() && !users.contains(&from) => {
+                Err(Denial::AddressNotAuthorized { address: from })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Maps the error of an estimation without credentials. With credentials, callers estimate
+    /// their own transactions and get the same details as from eth_call.
+    pub fn public_error(&self, err: ClientError) -> ErrorObjectOwned {
+        match err {
+            ClientError::Call(err) if self.scrub_reverts => {
+                // The sequencer puts the revert reason (and sometimes balances) in the message.
+                let message = if is_revert(&err) {
+                    "execution reverted"
+                } else {
+                    "gas estimation failed"
+                };
+                ErrorObject::owned(err.code(), message, None::<()>)
+            }
+            err => upstream_call_error(err),
+        }
+    }
+}
+
+impl Default for EstimationConfig {
+    fn default() -> Self {
+        EstimationConfig {
+            public: PublicEstimation::default(),
+            scrub_reverts: EstimationConfig::default_scrub_reverts(),
+        }
+    }
+}
+
+// Code 3 is used for reverts with data, older nodes only put it in the message.
+fn is_revert(err: &ErrorObjectOwned) -> bool {
+    err.code() == 3 || err.message().starts_with("execution reverted")
+}
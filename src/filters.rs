@@ -0,0 +1,315 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use serde::Deserialize;
+use zksync_types::{api::BlockNumber, Address, U256, U64};
+use zksync_web3_decl::{
+    client::{Client, L2},
+    jsonrpsee::{
+        core::RpcResult,
+        types::{ErrorObject, ErrorObjectOwned},
+    },
+    namespaces::EthNamespaceClient,
+    types::{Filter, FilterChanges, Log},
+};
+
+use crate::error::upstream_error;
+use crate::metrics::observe_upstream;
+use crate::policy::Policy;
+use crate::rate_limit::RATE_LIMITED_CODE;
+
+// Same code and message as geth, so that client libraries recognize it (and re-install the filter).
+pub const FILTER_NOT_FOUND_CODE: i32 = -32000;
+pub const FILTER_NOT_FOUND_MSG: &str = "filter not found";
+
+// If the filter wasn't polled for more than this many blocks, older ones are skipped.
+const MAX_BLOCKS_PER_POLL: u64 = 100;
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct FilterConfig {
+    // Filters that were not polled for this long are removed (like in geth).
+    #[serde(default = "FilterConfig::default_idle_timeout_secs")]
+    pub idle_timeout_secs: u64,
+    // Maximum number of filters installed by a single credential.
+    #[serde(default = "FilterConfig::default_max_filters_per_owner")]
+    pub max_filters_per_owner: usize,
+    // Maximum number of filters installed without credentials. Public users can't be told
+    // apart, so when it's reached, the filter polled least recently is removed.
+    #[serde(default = "FilterConfig::default_max_public_filters")]
+    pub max_public_filters: usize,
+}
+
+impl FilterConfig {
+    fn default_idle_timeout_secs() -> u64 {
+        300
+    }
+
+    fn default_max_filters_per_owner() -> usize {
+        100
+    }
+
+    fn default_max_public_filters() -> usize {
+        1000
+    }
+}
+
+impl Default for FilterConfig {
+    fn default() -> Self {
+        FilterConfig {
+            idle_timeout_secs: FilterConfig::default_idle_timeout_secs(),
+            max_filters_per_owner: FilterConfig::default_max_filters_per_owner(),
+            max_public_filters: FilterConfig::default_max_public_filters(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum FilterKind {
+    Blocks,
+    Logs(Filter),
+}
+
+impl FilterKind {
+    // First block that the filter reports (its 'fromBlock').
+    fn first_block(&self) -> u64 {
+        match self {
+            FilterKind::Logs(Filter {
+                from_block: Some(BlockNumber::Number(from)),
+                ..
+            }) => from.as_u64(),
+            _ => 0,
+        }
+    }
+
+    // Last block that the filter reports, given the latest one - its 'toBlock' if that's a
+    // number. A filter for a block hash only reports it once (on the first poll).
+    fn last_block(&self, latest: u64) -> u64 {
+        match self {
+            FilterKind::Logs(filter) if filter.block_hash.is_some() => u64::MAX,
+            FilterKind::Logs(Filter {
+                to_block: Some(BlockNumber::Number(to)),
+                ..
+            }) => to.as_u64().min(latest),
+            FilterKind::Logs(Filter {
+                to_block: Some(BlockNumber::Earliest),
+                ..
+            }) => 0,
+            _ => latest,
+        }
+    }
+
+    fn no_changes(&self) -> FilterChanges {
+        match self {
+            FilterKind::Blocks => FilterChanges::Hashes(vec![]),
+            FilterKind::Logs(_) => FilterChanges::Logs(vec![]),
+        }
+    }
+}
+
+struct InstalledFilter {
+    // Credentials that installed the filter (None for public filters).
+    owner: Option<String>,
+    kind: FilterKind,
+    // Last block whose changes were already returned.
+    last_block: u64,
+    last_poll: Instant,
+}
+
+/// Filters installed through eth_newFilter / eth_newBlockFilter.
+///
+/// The proxy polls the sequencer itself (instead of installing the filters upstream), so that
+/// results go through the same privacy rules as everything else. Filters can only be used by
+/// the credential that installed them.
+pub struct FilterRegistry {
+    config: FilterConfig,
+    filters: Mutex<HashMap<U256, InstalledFilter>>,
+}
+
+fn filter_not_found() -> ErrorObjectOwned {
+    ErrorObject::owned(FILTER_NOT_FOUND_CODE, FILTER_NOT_FOUND_MSG, None::<()>)
+}
+
+fn too_many_filters() -> ErrorObjectOwned {
+    ErrorObject::owned(RATE_LIMITED_CODE, "too many filters installed", None::<()>)
+}
+
+impl FilterRegistry {
+    pub fn new(config: FilterConfig) -> Self {
+        FilterRegistry {
+            config,
+            filters: Default::default(),
+        }
+    }
+
+    fn remove_expired(&self, filters: &mut HashMap<U256, InstalledFilter>) {
+        let idle_timeout = Duration::from_secs(self.config.idle_timeout_secs);
+        filters.retain(|_, filter| filter.last_poll.elapsed() < idle_timeout);
+    }
+
+    /// Installs a new filter - changes are reported starting from the next block.
+    pub async fn install(
+        &self,
+        client: &Client<L2>,
+        owner: Option<&str>,
+        kind: FilterKind,
+    ) -> RpcResult<U256> {
+        let current_block = observe_upstream("eth_blockNumber", client.get_block_number())
+            .await
+            .map_err(upstream_error)?;
+
+        let mut filters = self.filters.lock().unwrap();
+        self.remove_expired(&mut filters);
+        if owner.is_some() {
+            let installed = filters
+                .values()
+                .filter(|filter| filter.owner.as_deref() == owner)
+                .count();
+            if installed >= self.config.max_filters_per_owner {
+                return Err(too_many_filters());
+            }
+        } else {
+            if self.config.max_public_filters == 0 {
+                return Err(too_many_filters());
+            }
+            let public: Vec<(U256, Instant)> = filters
+                .iter()
+                .filter(|(_, filter)| filter.owner.is_none())
+                .map(|(id, filter)| (*id, filter.last_poll))
+                .collect();
+            if public.len() >= self.config.max_public_filters {
+                if let Some((id, _)) = public.iter().min_by_key(|(_, last_poll)| *last_poll) {
+                    filters.remove(id);
+                }
+            }
+        }
+
+        // Random ids, so that filters of other users can't be guessed.
+        let id = U256::from(rand::random::<u128>());
+        filters.insert(
+            id,
+            InstalledFilter {
+                owner: owner.map(|owner| owner.to_string()),
+                kind,
+                last_block: current_block.as_u64(),
+                last_poll: Instant::now(),
+            },
+        );
+        Ok(id)
+    }
+
+    /// Returns false if there is no such filter (or it belongs to someone else).
+    pub fn uninstall(&self, owner: Option<&str>, id: U256) -> bool {
+        let mut filters = self.filters.lock().unwrap();
+        self.remove_expired(&mut filters);
+        match filters.get(&id) {
+            Some(filter) if filter.owner.as_deref() == owner => {
+                filters.remove(&id);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns what happened since the last poll: new block hashes, or logs that
+    /// are visible to `users` (or to everyone, if None).
+    pub async fn changes(
+        &self,
+        client: &Client<L2>,
+        policy: &dyn Policy,
+        owner: Option<&str>,
+        users: Option<&HashSet<Address>>,
+        id: U256,
+    ) -> RpcResult<FilterChanges> {
+        let kind = {
+            let mut filters = self.filters.lock().unwrap();
+            self.remove_expired(&mut filters);
+            match filters.get_mut(&id) {
+                Some(filter) if filter.owner.as_deref() == owner => {
+                    filter.last_poll = Instant::now();
+                    filter.kind.clone()
+                }
+                _ => return Err(filter_not_found()),
+            }
+        };
+
+        let latest = observe_upstream("eth_blockNumber", client.get_block_number())
+            .await
+            .map_err(upstream_error)?
+            .as_u64();
+        // The range is taken under the lock, so that concurrent polls don't both return it.
+        let (from, to, previous) = {
+            let mut filters = self.filters.lock().unwrap();
+            // The filter might have been uninstalled in the meantime.
+            let Some(filter) = filters.get_mut(&id) else {
+                return Err(filter_not_found());
+            };
+            let previous = filter.last_block;
+            let to = kind.last_block(latest);
+            if previous >= to {
+                return Ok(kind.no_changes());
+            }
+            filter.last_block = to;
+            let from = (previous + 1)
+                .max(to.saturating_sub(MAX_BLOCKS_PER_POLL - 1))
+                .max(kind.first_block());
+            (from, to, previous)
+        };
+        if from > to {
+            return Ok(kind.no_changes());
+        }
+
+        let changes: RpcResult<FilterChanges> = async {
+            Ok(match kind {
+                FilterKind::Blocks => {
+                    let mut hashes = vec![];
+                    for number in from..=to {
+                        let block = observe_upstream(
+                            "eth_getBlockByNumber",
+                            client
+                                .get_block_by_number(BlockNumber::Number(U64::from(number)), false),
+                        )
+                        .await
+                        .map_err(upstream_error)?;
+                        if let Some(block) = block {
+                            hashes.push(block.hash);
+                        }
+                    }
+                    FilterChanges::Hashes(hashes)
+                }
+                FilterKind::Logs(filter) => {
+                    let filter = if filter.block_hash.is_some() {
+                        filter
+                    } else {
+                        Filter {
+                            from_block: Some(BlockNumber::Number(U64::from(from))),
+                            to_block: Some(BlockNumber::Number(U64::from(to))),
+                            ..filter
+                        }
+                    };
+                    let logs: Vec<Log> = observe_upstream("eth_getLogs", client.get_logs(filter))
+                        .await
+                        .map_err(upstream_error)?;
+                    FilterChanges::Logs(
+                        logs.into_iter()
+                            .filter(|log| policy.evaluate_log(log, users).is_allowed())
+                            .collect(),
+                    )
+                }
+            })
+        }
+        .await;
+
+        if changes.is_err() {
+            // Gives the range back for the next poll - unless another poll took more since.
+            if let Some(filter) = self.filters.lock().unwrap().get_mut(&id) {
+                if filter.last_block == to {
+                    filter.last_block = previous;
+                }
+            }
+        }
+        changes
+    }
+}
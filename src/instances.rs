@@ -0,0 +1,245 @@
+// Lookups for whitelist entries that match many contracts - by their bytecode hash, or by
+// the factory that deployed them (pairs, vaults, smart accounts). Every instance then gets
+// the methods of the entry.
+//
+// The bytecode hash is the versioned one used by zkSync (as shown by the explorer and emitted
+// in ContractDeployed), the deployer comes from the ContractDeployed event.
+
+use std::{
+    collections::HashSet,
+    num::NonZeroUsize,
+    str::FromStr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use lru::LruCache;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tokio::sync::Semaphore;
+use zksync_types::{
+    api::{BlockIdVariant, BlockNumber},
+    url::SensitiveUrl,
+    Address, CONTRACT_DEPLOYER_ADDRESS, H256, U64,
+};
+use zksync_web3_decl::{
+    client::{Client, L2},
+    jsonrpsee::core::ClientError,
+    namespaces::EthNamespaceClient,
+    types::{Filter, ValueOrArray},
+};
+
+use crate::config::WhitelistEntry;
+use crate::error::Denial;
+use crate::metrics::observe_upstream;
+
+// ContractDeployed(address indexed deployerAddress, bytes32 indexed bytecodeHash, address indexed contractAddress)
+const CONTRACT_DEPLOYED_TOPIC: &str =
+    "290afdae231a3fc0bbae8b1af63698b0a1d79b21ad17df0342dfb952fe74f8e5";
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct InstanceConfig {
+    // Addresses without code (they might get deployed) and failed lookups are looked up again
+    // after this long. Deployed contracts can't change, so they stay cached.
+    #[serde(default = "InstanceConfig::default_miss_ttl_ms")]
+    pub miss_ttl_ms: u64,
+    // Maximum number of cached lookups (0 disables the cache).
+    #[serde(default = "InstanceConfig::default_max_entries")]
+    pub max_entries: usize,
+    // Lookups made at the same time - others wait for them. Each one takes several upstream
+    // calls, and anyone can start them by calling contracts that are not cached yet.
+    #[serde(default = "InstanceConfig::default_max_concurrent_lookups")]
+    pub max_concurrent_lookups: usize,
+}
+
+impl InstanceConfig {
+    fn default_miss_ttl_ms() -> u64 {
+        10_000
+    }
+
+    fn default_max_entries() -> usize {
+        10_000
+    }
+
+    fn default_max_concurrent_lookups() -> usize {
+        8
+    }
+}
+
+impl Default for InstanceConfig {
+    fn default() -> Self {
+        InstanceConfig {
+            miss_ttl_ms: InstanceConfig::default_miss_ttl_ms(),
+            max_entries: InstanceConfig::default_max_entries(),
+            max_concurrent_lookups: InstanceConfig::default_max_concurrent_lookups(),
+        }
+    }
+}
+
+/// What the whitelist needs to know about a contract that is not listed by its address.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Instance {
+    pub code_hash: Option<H256>,
+    pub deployer: Option<Address>,
+}
+
+pub struct InstanceLookup {
+    sequencer_url: String,
+    // Only the lookups that some entry needs are made. Contracts whose code hash has an entry
+    // don't need the deployer.
+    code_hashes: HashSet<H256>,
+    factories: bool,
+    // Instance (None if the lookup failed), and when it expires (None for deployed contracts).
+    cache: Option<Mutex<LruCache<Address, (Option<Instance>, Option<Instant>)>>>,
+    miss_ttl: Duration,
+    lookups: Semaphore,
+}
+
+impl InstanceLookup {
+    pub fn new(sequencer_url: &str, entries: &[WhitelistEntry], config: &InstanceConfig) -> Self {
+        InstanceLookup {
+            sequencer_url: sequencer_url.to_string(),
+            code_hashes: entries
+                .iter()
+                .filter_map(|entry| entry.code_hash.as_deref())
+                .filter_map(|code_hash| H256::from_str(code_hash).ok())
+                .collect(),
+            factories: entries.iter().any(|entry| entry.factory.is_some()),
+            cache: NonZeroUsize::new(config.max_entries)
+                .map(|size| Mutex::new(LruCache::new(size))),
+            miss_ttl: Duration::from_millis(config.miss_ttl_ms),
+            lookups: Semaphore::new(config.max_concurrent_lookups.max(1)),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.code_hashes.is_empty() && !self.factories
+    }
+
+    pub fn create_client(&self) -> Client<L2> {
+        let url = SensitiveUrl::from_str(&self.sequencer_url)
+            .unwrap_or_else(|_| panic!("Unable to parse client URL: {}", &self.sequencer_url));
+        Client::http(url)
+            .unwrap_or_else(|_| {
+                panic!("Unable to create a client for fork: {}", self.sequencer_url)
+            })
+            .build()
+    }
+
+    fn cached(&self, contract: &Address) -> Option<Result<Instance, Denial>> {
+        let mut cache = self.cache.as_ref()?.lock().unwrap();
+        match cache.get(contract) {
+            Some((instance, expires_at)) if expires_at.map_or(true, |at| at > Instant::now()) => {
+                Some(instance.ok_or(Denial::InstanceLookupFailed {
+                    contract: *contract,
+                }))
+            }
+            _ => None,
+        }
+    }
+
+    /// Bytecode hash and deployer of the contract (both None if it has no code).
+    pub async fn instance(&self, contract: Address) -> Result<Instance, Denial> {
+        if self.is_empty() {
+            return Ok(Instance::default());
+        }
+        if let Some(instance) = self.cached(&contract) {
+            return instance;
+        }
+        let _permit = self.lookups.acquire().await.unwrap();
+        // It might have been looked up while waiting.
+        if let Some(instance) = self.cached(&contract) {
+            return instance;
+        }
+
+        let result = self.lookup(contract).await;
+        if let Some(cache) = &self.cache {
+            let (instance, expires_at) = match &result {
+                Ok((instance, true)) => (Some(*instance), None),
+                Ok((instance, false)) => (Some(*instance), Some(Instant::now() + self.miss_ttl)),
+                Err(_) => (None, Some(Instant::now() + self.miss_ttl)),
+            };
+            cache.lock().unwrap().put(contract, (instance, expires_at));
+        }
+        match result {
+            Ok((instance, _)) => Ok(instance),
+            Err(err) => {
+                tracing::warn!(contract = ?contract, "Instance lookup failed: {}", err);
+                Err(Denial::InstanceLookupFailed { contract })
+            }
+        }
+    }
+
+    // Instance of the contract, and whether it has code.
+    async fn lookup(&self, contract: Address) -> Result<(Instance, bool), ClientError> {
+        let client = self.create_client();
+        let code = observe_upstream("eth_getCode", client.get_code(contract, None)).await?;
+        let mut instance = Instance::default();
+        if !code.0.is_empty() {
+            let code_hash = bytecode_hash(&code.0);
+            if !self.code_hashes.is_empty() {
+                instance.code_hash = Some(code_hash);
+            }
+            if self.factories && !self.code_hashes.contains(&code_hash) {
+                let deployed_in = self.deployment_block(&client, contract).await?;
+                let filter = Filter {
+                    from_block: Some(BlockNumber::Number(deployed_in)),
+                    to_block: Some(BlockNumber::Number(deployed_in)),
+                    address: Some(ValueOrArray(vec![CONTRACT_DEPLOYER_ADDRESS])),
+                    topics: Some(vec![
+                        Some(ValueOrArray(vec![
+                            H256::from_str(CONTRACT_DEPLOYED_TOPIC).unwrap()
+                        ])),
+                        None,
+                        None,
+                        Some(ValueOrArray(vec![H256::from(contract)])),
+                    ]),
+                    block_hash: None,
+                };
+                let logs = observe_upstream("eth_getLogs", client.get_logs(filter)).await?;
+                instance.deployer = logs
+                    .first()
+                    .and_then(|log| log.topics.get(1))
+                    .map(|topic| Address::from_slice(&topic.0[12..]));
+            }
+        }
+        Ok((instance, !code.0.is_empty()))
+    }
+
+    // First block in which the contract has code - found by bisection, so that the
+    // ContractDeployed event is searched for in one block instead of the whole chain.
+    async fn deployment_block(
+        &self,
+        client: &Client<L2>,
+        contract: Address,
+    ) -> Result<U64, ClientError> {
+        let mut first = 0;
+        // The contract has code in this block.
+        let mut last = observe_upstream("eth_blockNumber", client.get_block_number())
+            .await?
+            .as_u64();
+        while first < last {
+            let middle = first + (last - first) / 2;
+            let block = BlockIdVariant::BlockNumber(BlockNumber::Number(U64::from(middle)));
+            let code =
+                observe_upstream("eth_getCode", client.get_code(contract, Some(block))).await?;
+            if code.0.is_empty() {
+                first = middle + 1;
+            } else {
+                last = middle;
+            }
+        }
+        Ok(U64::from(last))
+    }
+}
+
+// Versioned bytecode hash: sha256 of the code, with the version (1) and the length
+// in 32-byte words in the first 4 bytes.
+fn bytecode_hash(code: &[u8]) -> H256 {
+    let mut hash: [u8; 32] = Sha256::digest(code).into();
+    let words = (code.len() / 32) as u16;
+    hash[0] = 1;
+    hash[1] = 0;
+    hash[2..4].copy_from_slice(&words.to_be_bytes());
+    H256(hash)
+}
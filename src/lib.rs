@@ -0,0 +1,35 @@
+//! doubleo - a privacy proxy for a ZKsync sequencer.
+//!
+//! The binary (`src/main.rs`) is a thin CLI on top of this crate. To embed the proxy,
+//! build it with `ProxyBuilder` and either `start` it or mount its `rpc` module
+//! (together with `AuthMiddlewareLayer`) on your own server.
+
+pub mod admin;
+pub mod audit;
+pub mod builder;
+pub mod cache;
+pub mod config;
+pub mod error;
+pub mod estimation;
+pub mod filters;
+pub mod instances;
+pub mod logging;
+pub mod metrics;
+pub mod middleware;
+pub mod ownership;
+pub mod policy;
+pub mod proxy;
+pub mod pubsub;
+pub mod rate_limit;
+pub mod redaction;
+pub mod response_filter;
+pub mod rewrite;
+pub mod script;
+pub mod whitelist;
+
+pub use builder::{BuiltProxy, ProxyBuilder, RunningProxy};
+pub use config::{Config, Methods, WhitelistEntry};
+pub use middleware::AuthMiddlewareLayer;
+pub use policy::{Decision, Policy};
+pub use proxy::{PrivateProxy, Proxy};
+pub use whitelist::ContractWhitelist;
@@ -0,0 +1,108 @@
+use std::{
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    task::{Context, Poll},
+};
+
+use futures::future::BoxFuture;
+use hyper::{Body, Request, Response};
+use serde_json::Value;
+use tower::{Layer, Service};
+use tracing::Instrument;
+use tracing_subscriber::EnvFilter;
+
+use crate::audit::credential_hash;
+use crate::middleware::get_credentials_from_request;
+
+// If false (default), credentials are only ever logged as fingerprints.
+static LOG_SECRETS: AtomicBool = AtomicBool::new(false);
+
+static NEXT_REQUEST_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Sets up the global subscriber. Log level can be changed with RUST_LOG (default: info).
+pub fn init(json: bool, log_secrets: bool) {
+    LOG_SECRETS.store(log_secrets, Ordering::Relaxed);
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+    if json {
+        builder.json().init();
+    } else {
+        builder.init();
+    }
+}
+
+/// How credentials should appear in the logs - a short hash, unless secrets logging was enabled.
+pub fn credential_for_log(credentials: &str) -> String {
+    if LOG_SECRETS.load(Ordering::Relaxed) {
+        credentials.to_string()
+    } else {
+        credential_hash(credentials)[..8].to_string()
+    }
+}
+
+fn request_method(body: &Value) -> String {
+    match body {
+        Value::Array(_) => "batch".to_string(),
+        call => call["method"].as_str().unwrap_or("-").to_string(),
+    }
+}
+
+// Middleware that runs every request inside a span with request id, method and credential.
+#[derive(Clone)]
+pub struct RequestSpanMiddleware<S> {
+    inner: S,
+}
+
+impl<S> Service<Request<Body>> for RequestSpanMiddleware<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>> + Send + Clone + 'static,
+    S::Future: Send,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let mut inner = self.inner.clone();
+        Box::pin(async move {
+            let request_id = NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed);
+            let credential = get_credentials_from_request(&req)
+                .map(|credentials| credential_for_log(&credentials))
+                .unwrap_or_else(|| "-".to_string());
+            let (parts, body) = req.into_parts();
+            let body_bytes = hyper::body::to_bytes(body).await.unwrap_or_default();
+            let method = serde_json::from_slice::<Value>(&body_bytes)
+                .map(|body| request_method(&body))
+                .unwrap_or_else(|_| "-".to_string());
+
+            let span = tracing::info_span!(
+                "request",
+                id = request_id,
+                method = %method,
+                credential = %credential
+            );
+            async move {
+                tracing::debug!("Received request");
+                inner
+                    .call(Request::from_parts(parts, Body::from(body_bytes)))
+                    .await
+            }
+            .instrument(span)
+            .await
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct RequestSpanLayer;
+
+impl<S> Layer<S> for RequestSpanLayer {
+    type Service = RequestSpanMiddleware<S>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        RequestSpanMiddleware { inner: service }
+    }
+}
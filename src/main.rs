@@ -1,17 +1,8 @@
-use std::fs;
+use std::time::Duration;
 
 use clap::{Parser, Subcommand};
-use middleware::AuthMiddlewareLayer;
-use proxy::{PrivateEthNamespaceServer, PrivateProxy};
-use serde::Deserialize;
-use whitelist::ContractWhitelist;
-use zksync_web3_decl::jsonrpsee::server::ServerBuilder;
-use zksync_web3_decl::{jsonrpsee::RpcModule, namespaces::EthNamespaceServer};
-mod proxy;
-mod whitelist;
-use crate::proxy::Proxy;
-
-mod middleware;
+use doubleo::{audit, config::parse_config, logging, Config, ProxyBuilder};
+
 #[derive(Debug, Parser)]
 #[command(author = "Matter Labs", version, about = "Proxy", long_about = None)]
 struct Cli {
@@ -21,96 +12,111 @@ struct Cli {
     /// Port to listen on - default: 8011
     port: u16,
 
+    /// Required for 'run'.
     #[arg(long)]
-    sequencer_url: String,
+    sequencer_url: Option<String>,
 
     #[arg(long, default_value = "config.yaml")]
     config_file_path: String,
+
+    /// Output logs as JSON.
+    #[arg(long)]
+    log_json: bool,
+
+    /// Log credentials in plain text (by default only their fingerprint is logged).
+    #[arg(long)]
+    log_secrets: bool,
 }
 
 #[derive(Debug, Subcommand)]
 enum Command {
     #[command(name = "run")]
     Run,
+    #[command(name = "audit")]
+    Audit {
+        #[command(subcommand)]
+        command: AuditCommand,
+    },
 }
 
-#[derive(Debug, Deserialize, Clone)]
-pub struct WhitelistEntry {
-    address: String,
-    fully_whitelisted: bool,
-    methods: Option<Methods>,
-}
-
-#[derive(Debug, Deserialize, Clone)]
-pub struct Methods {
-    unrestricted: Option<Vec<String>>,
-    requires_authorization: Option<Vec<String>>,
-}
-
-#[derive(Deserialize, Debug)]
-struct Config {
-    // If enabled, allows anyone to create new contracts.
-    allow_contract_creation: bool,
-
-    // List of contracts that can be called with 'call'.
-    whitelist: Vec<WhitelistEntry>,
-}
-
-fn parse_config(path: &str) -> eyre::Result<Config> {
-    let file_content = fs::read_to_string(path).expect("Unable to read file");
-
-    // Parse the YAML string into the Config struct
-    let config: Config = serde_yaml::from_str(&file_content)?;
-
-    return Ok(config);
+#[derive(Debug, Subcommand)]
+enum AuditCommand {
+    /// Verifies the hash chain of the audit log.
+    #[command(name = "verify")]
+    Verify {
+        /// Defaults to 'audit_log' from the config.
+        #[arg(long)]
+        file: Option<String>,
+    },
 }
 
 #[tokio::main]
 async fn main() -> eyre::Result<()> {
     let opt = Cli::parse();
-    let config = parse_config(&opt.config_file_path).unwrap();
-
-    println!("config: {:?}", config);
-    tracing_subscriber::fmt::init();
-
-    let proxy = Proxy {
-        sequencer_url: opt.sequencer_url.clone(),
-        whitelist: ContractWhitelist::init(config.whitelist.clone()),
-    };
-
-    let private_proxy = PrivateProxy {
-        sequencer_url: opt.sequencer_url,
-        whitelist: ContractWhitelist::init(config.whitelist),
-        credentials: Default::default(),
-    };
-
-    let mut rpc = RpcModule::new(());
-    rpc.merge(proxy.into_rpc()).unwrap();
-    rpc.merge(private_proxy.into_rpc()).unwrap();
-
-    let cors_layer = tower_http::cors::CorsLayer::very_permissive();
-
-    let http_middleware = tower::ServiceBuilder::new()
-        .layer(AuthMiddlewareLayer {})
-        .layer(cors_layer);
-
-    // Create the server with custom middleware
-    let builder = ServerBuilder::default();
-    let server = builder
-        .set_http_middleware(http_middleware)
-        .http_only()
-        .build(format!("127.0.0.1:{:?}", opt.port))
-        .await
-        .unwrap();
+    let config = parse_config(&opt.config_file_path)?;
+
+    match &opt.command {
+        Command::Run => run(opt, config).await,
+        Command::Audit {
+            command: AuditCommand::Verify { file },
+        } => {
+            let path = file
+                .clone()
+                .or(config.audit_log)
+                .ok_or_else(|| eyre::eyre!("No audit log file given (--file or 'audit_log')"))?;
+            match audit::verify(&path)? {
+                Some(last_hash) => {
+                    println!("Audit log {} is valid, last hash: {}", path, last_hash)
+                }
+                None => println!("Audit log {} is empty", path),
+            }
+            Ok(())
+        }
+    }
+}
 
-    let handle = server.start(rpc);
+async fn run(opt: Cli, config: Config) -> eyre::Result<()> {
+    let sequencer_url = opt
+        .sequencer_url
+        .ok_or_else(|| eyre::eyre!("--sequencer-url is required"))?;
+
+    logging::init(opt.log_json, opt.log_secrets);
+    tracing::debug!("config: {:?}", config);
+
+    let node = ProxyBuilder::new(sequencer_url, config)
+        .config_path(&opt.config_file_path)
+        .build()
+        .await?
+        .start(&format!("127.0.0.1:{}", opt.port))
+        .await?;
+
+    let cache = node.cache.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(60));
+        loop {
+            interval.tick().await;
+            let stats = cache.stats();
+            tracing::info!(
+                "Cache: {} hits, {} misses, hit ratio {:.2}",
+                stats.hits,
+                stats.misses,
+                stats.hit_ratio()
+            );
+        }
+    });
 
     tracing::info!("========================================");
-    tracing::info!("  Node is ready at 127.0.0.1:{}", opt.port);
+    tracing::info!("  Node is ready at {}", node.local_addr);
+    if let Some(admin_addr) = node.admin_addr {
+        tracing::info!("  Admin namespace at {}", admin_addr);
+    }
+    if let Some(metrics_addr) = node.metrics_addr {
+        tracing::info!("  Metrics at http://{}/metrics", metrics_addr);
+    }
     tracing::info!("========================================");
 
     // Wait for the server to finish
-    handle.stopped().await;
+    node.handle.stopped().await;
 
     Ok(())
 }
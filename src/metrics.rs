@@ -0,0 +1,262 @@
+use std::{
+    collections::HashSet,
+    convert::Infallible,
+    future::Future,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use futures::future::BoxFuture;
+use hyper::{header, Body, Method, Request, Response, StatusCode};
+use once_cell::sync::Lazy;
+use prometheus::{
+    register_histogram_vec, register_int_counter_vec, register_int_gauge, Encoder, HistogramVec,
+    IntCounterVec, IntGauge, TextEncoder,
+};
+use serde::Deserialize;
+use serde_json::Value;
+use tower::{Layer, Service};
+use zksync_web3_decl::jsonrpsee::core::ClientError;
+
+use crate::error::Denial;
+
+pub const METRICS_PATH: &str = "/metrics";
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct MetricsConfig {
+    // Address of the metrics server, e.g. "127.0.0.1:9100".
+    pub address: Option<String>,
+}
+
+static REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "doubleo_requests_total",
+        "Number of JSON-RPC calls received, per method",
+        &["method"]
+    )
+    .unwrap()
+});
+
+static REQUEST_LATENCY: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "doubleo_request_duration_seconds",
+        "Time to serve a JSON-RPC request, per method",
+        &["method"]
+    )
+    .unwrap()
+});
+
+static WHITELIST_DECISIONS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "doubleo_whitelist_decisions_total",
+        "Whitelist decisions for calls, by kind (authorized/unauthorized) and reason",
+        &["kind", "decision", "reason"]
+    )
+    .unwrap()
+});
+
+static DENIALS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "doubleo_denials_total",
+        "Requests denied by the proxy, by reason",
+        &["reason"]
+    )
+    .unwrap()
+});
+
+static UPSTREAM_REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "doubleo_upstream_requests_total",
+        "Requests sent to the sequencer, by method and result (ok, rpc_error, transport_error)",
+        &["method", "result"]
+    )
+    .unwrap()
+});
+
+static UPSTREAM_LATENCY: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "doubleo_upstream_duration_seconds",
+        "Latency of requests sent to the sequencer, per method",
+        &["method"]
+    )
+    .unwrap()
+});
+
+static ACTIVE_CREDENTIALS: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "doubleo_active_credentials",
+        "Number of credentials known to the proxy"
+    )
+    .unwrap()
+});
+
+static MIDDLEWARE_REWRITES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "doubleo_middleware_rewrites_total",
+        "Requests rewritten by the auth middleware into their private versions",
+        &["from", "to"]
+    )
+    .unwrap()
+});
+
+static CACHE_REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "doubleo_cache_requests_total",
+        "Response cache lookups, by method and result (hit, miss)",
+        &["method", "result"]
+    )
+    .unwrap()
+});
+
+pub fn record_whitelist_decision(kind: &str, result: &Result<(), Denial>) {
+    let (decision, reason) = match result {
+        Ok(()) => ("allow", "allowed"),
+        Err(denial) => ("deny", denial.reason()),
+    };
+    WHITELIST_DECISIONS
+        .with_label_values(&[kind, decision, reason])
+        .inc();
+}
+
+pub fn record_denial(denial: &Denial) {
+    DENIALS.with_label_values(&[denial.reason()]).inc();
+}
+
+pub fn set_active_credentials(count: usize) {
+    ACTIVE_CREDENTIALS.set(count as i64);
+}
+
+pub fn record_rewrite(from: &str, to: &str) {
+    MIDDLEWARE_REWRITES.with_label_values(&[from, to]).inc();
+}
+
+pub fn record_cache_lookup(method: &str, hit: bool) {
+    let result = if hit { "hit" } else { "miss" };
+    CACHE_REQUESTS.with_label_values(&[method, result]).inc();
+}
+
+/// Sends the request to the sequencer, recording its latency and result.
+pub async fn observe_upstream<T>(
+    method: &str,
+    request: impl Future<Output = Result<T, ClientError>>,
+) -> Result<T, ClientError> {
+    let start = Instant::now();
+    let result = request.await;
+    UPSTREAM_LATENCY
+        .with_label_values(&[method])
+        .observe(start.elapsed().as_secs_f64());
+    let status = match &result {
+        Ok(_) => "ok",
+        Err(ClientError::Call(_)) => "rpc_error",
+        Err(_) => "transport_error",
+    };
+    UPSTREAM_REQUESTS.with_label_values(&[method, status]).inc();
+    result
+}
+
+/// Records a served JSON-RPC call. Unknown methods are reported as 'other', so that clients
+/// can't blow up the number of label values.
+pub fn record_request(method: &str, known_methods: &HashSet<String>, elapsed: f64) {
+    let method = if known_methods.contains(method) {
+        method
+    } else {
+        "other"
+    };
+    REQUESTS.with_label_values(&[method]).inc();
+    REQUEST_LATENCY
+        .with_label_values(&[method])
+        .observe(elapsed);
+}
+
+/// Serves `GET /metrics` on the metrics address.
+pub async fn serve(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    if req.method() != Method::GET || req.uri().path() != METRICS_PATH {
+        return Ok(Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty())
+            .unwrap());
+    }
+    let mut buffer = vec![];
+    let encoder = TextEncoder::new();
+    if let Err(err) = encoder.encode(&prometheus::gather(), &mut buffer) {
+        tracing::warn!("Failed to encode metrics: {}", err);
+    }
+    Ok(Response::builder()
+        .header(header::CONTENT_TYPE, encoder.format_type())
+        .body(Body::from(buffer))
+        .unwrap())
+}
+
+// Method names of all calls in the request.
+fn request_methods(body: &Value) -> Vec<String> {
+    let calls: Vec<&Value> = match body {
+        Value::Array(calls) => calls.iter().collect(),
+        call => vec![call],
+    };
+    calls
+        .into_iter()
+        .map(|call| call["method"].as_str().unwrap_or_default().to_string())
+        .collect()
+}
+
+// Middleware that records per-method request metrics.
+#[derive(Clone)]
+pub struct MetricsMiddleware<S> {
+    inner: S,
+    known_methods: Arc<HashSet<String>>,
+}
+
+impl<S> Service<Request<Body>> for MetricsMiddleware<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>> + Send + Clone + 'static,
+    S::Future: Send,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let mut inner = self.inner.clone();
+        let known_methods = self.known_methods.clone();
+        Box::pin(async move {
+            let start = Instant::now();
+            let (parts, body) = req.into_parts();
+            let body_bytes = hyper::body::to_bytes(body).await.unwrap_or_default();
+            let methods = serde_json::from_slice::<Value>(&body_bytes)
+                .map(|body| request_methods(&body))
+                .unwrap_or_default();
+
+            let response = inner
+                .call(Request::from_parts(parts, Body::from(body_bytes)))
+                .await;
+
+            let elapsed = start.elapsed().as_secs_f64();
+            for method in &methods {
+                record_request(method, &known_methods, elapsed);
+            }
+            response
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct MetricsLayer {
+    // Names of the methods served by the proxy - used as label values.
+    pub known_methods: Arc<HashSet<String>>,
+}
+
+impl<S> Layer<S> for MetricsLayer {
+    type Service = MetricsMiddleware<S>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        MetricsMiddleware {
+            inner: service,
+            known_methods: self.known_methods.clone(),
+        }
+    }
+}
@@ -1,7 +1,4 @@
-use std::borrow::Cow;
-
 use serde::Serialize;
-use serde_json::{json, value::RawValue, Value};
 use tower::Service;
 
 use base64::decode;
@@ -10,9 +7,12 @@ use hyper::{Body, Request, Response};
 use std::task::{Context, Poll};
 use tower::Layer;
 
-use zksync_web3_decl::jsonrpsee::http_client::types::Request as JsonRpcRequest;
+use crate::metrics;
+use crate::rewrite::rewrite_body;
+use crate::rewrite::PRIVATE_METHODS;
 
 // Custom middleware to intercept and modify requests
+#[derive(Clone)]
 pub struct AuthMiddleware<S> {
     inner: S,
 }
@@ -26,15 +26,10 @@ struct AuthInfo {
 use once_cell::sync::Lazy;
 use std::collections::HashMap;
 
-static REQUEST_AUTH_MAP: Lazy<HashMap<&'static str, &'static str>> = Lazy::new(|| {
-    let mut m = HashMap::new();
-    m.insert("eth_getBalance", "privateeth_getBalance");
-    m.insert("eth_blockNumber", "privateeth_blockNumber");
-    m.insert("eth_call", "privateeth_call");
-    m
-});
+static REQUEST_AUTH_MAP: Lazy<HashMap<&'static str, &'static str>> =
+    Lazy::new(|| PRIVATE_METHODS.iter().copied().collect());
 
-fn get_credentials_from_request(req: &Request<Body>) -> Option<String> {
+pub fn get_credentials_from_request(req: &Request<Body>) -> Option<String> {
     if let Some(auth_header) = req.headers().get("authorization") {
         if let Ok(auth_str) = auth_header.to_str() {
             if auth_str.starts_with("Basic ") {
@@ -75,46 +70,21 @@ where
         Box::pin(async move {
             if let Some(credentials) = get_credentials_from_request(&req) {
                 // Intercept and modify JSON-RPC requests
-                let uri = req.uri().clone();
                 let (parts, body) = req.into_parts();
-                let body_bytes = hyper::body::to_bytes(body).await.unwrap();
-
-                if body_bytes.len() > 0 {
-                    let mut json_rpc_request: JsonRpcRequest = serde_json::from_slice(&body_bytes)
-                        .expect(&format!("Failed to parse {:?}  {:?}", uri, body_bytes));
-
-                    if let Some(private_method) =
-                        REQUEST_AUTH_MAP.get(json_rpc_request.method_name())
-                    {
-                        json_rpc_request.method = (*private_method).into();
-
-                        let mut params_json = if let Some(prev) = json_rpc_request.params {
-                            let value: Value = serde_json::from_str(prev.get()).unwrap();
-
-                            value
-                        } else {
-                            Value::Array(vec![])
-                        };
+                let body_bytes = hyper::body::to_bytes(body).await.unwrap_or_default();
 
-                        if let Some(arr) = params_json.as_array_mut() {
-                            arr.insert(0, json!(credentials));
-                        }
-
-                        let modified_json_string = serde_json::to_string(&params_json).unwrap();
-
-                        let new_raw_value = RawValue::from_string(modified_json_string).unwrap();
-
-                        json_rpc_request.params = Some(Cow::Owned(new_raw_value));
+                let body = rewrite_body(&body_bytes, &credentials, |method| {
+                    let private_method = REQUEST_AUTH_MAP.get(method).copied();
+                    if let Some(private_method) = private_method {
+                        metrics::record_rewrite(method, private_method);
                     }
+                    private_method
+                })
+                .unwrap_or_else(|| body_bytes.to_vec());
 
-                    let modified_body = serde_json::to_vec(&json_rpc_request).unwrap();
-                    let modified_req = Request::from_parts(parts, Body::from(modified_body));
-
-                    return inner.call(modified_req).await;
-                } else {
-                    let modified_req = Request::from_parts(parts, Body::from(vec![]));
-                    return inner.call(modified_req).await;
-                }
+                return inner
+                    .call(Request::from_parts(parts, Body::from(body)))
+                    .await;
             }
 
             inner.call(req).await
@@ -123,6 +93,7 @@ where
 }
 
 // Layer implementation for AuthMiddleware
+#[derive(Clone)]
 pub struct AuthMiddlewareLayer;
 
 impl<S> Layer<S> for AuthMiddlewareLayer {
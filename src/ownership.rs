@@ -0,0 +1,209 @@
+// Ownership lookups for NFT- and position-like contracts, where the address isn't in the calldata.
+//
+// A rule maps a method (e.g. tokenURI(uint256)) to a lookup (e.g. ownerOf(uint256)) that is
+// called with one of its arguments. The call is allowed if the lookup returns one of the
+// caller's addresses. Lookups are cached for a short time.
+
+use std::{
+    collections::{HashMap, HashSet},
+    num::NonZeroUsize,
+    str::FromStr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use lru::LruCache;
+use serde::Deserialize;
+use zksync_types::{transaction_request::CallRequest, url::SensitiveUrl, web3::Bytes, Address};
+use zksync_web3_decl::{
+    client::{Client, L2},
+    jsonrpsee::core::async_trait,
+    namespaces::EthNamespaceClient,
+};
+
+use crate::config::{OwnerLookup, WhitelistEntry};
+use crate::error::Denial;
+use crate::metrics::observe_upstream;
+use crate::policy::{Decision, Policy};
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct OwnershipConfig {
+    // How long the owner returned by a lookup is trusted.
+    #[serde(default = "OwnershipConfig::default_ttl_ms")]
+    pub ttl_ms: u64,
+    // Maximum number of cached lookups (0 disables the cache).
+    #[serde(default = "OwnershipConfig::default_max_entries")]
+    pub max_entries: usize,
+}
+
+impl OwnershipConfig {
+    fn default_ttl_ms() -> u64 {
+        5000
+    }
+
+    fn default_max_entries() -> usize {
+        10_000
+    }
+}
+
+impl Default for OwnershipConfig {
+    fn default() -> Self {
+        OwnershipConfig {
+            ttl_ms: OwnershipConfig::default_ttl_ms(),
+            max_entries: OwnershipConfig::default_max_entries(),
+        }
+    }
+}
+
+// Lookup contract, lookup selector and the argument it was called with.
+type LookupKey = (Address, [u8; 4], [u8; 32]);
+
+struct Rule {
+    lookup: [u8; 4],
+    arg: usize,
+}
+
+/// Allows calls of the owner (per the lookup rules of the whitelist entries).
+/// Abstains for methods without a rule and for callers without credentials.
+pub struct OwnershipPolicy {
+    sequencer_url: String,
+    // Rules per contract, keyed by the selector (hex) of the guarded method.
+    rules: HashMap<Address, HashMap<String, Rule>>,
+    cache: Option<Mutex<LruCache<LookupKey, (Option<Address>, Instant)>>>,
+    ttl: Duration,
+}
+
+impl OwnershipPolicy {
+    pub fn new(
+        sequencer_url: &str,
+        entries: &[WhitelistEntry],
+        config: &OwnershipConfig,
+    ) -> eyre::Result<Self> {
+        let mut rules = HashMap::new();
+        for entry in entries {
+            let Some(lookups) = entry
+                .methods
+                .as_ref()
+                .and_then(|methods| methods.owner_lookup.as_ref())
+            else {
+                continue;
+            };
+            let Some(address) = &entry.address else {
+                continue;
+            };
+            let address = Address::from_str(address)?;
+            let contract_rules: &mut HashMap<_, _> = rules.entry(address).or_default();
+            for OwnerLookup {
+                selector,
+                lookup,
+                arg,
+            } in lookups
+            {
+                let mut rule = Rule {
+                    lookup: [0; 4],
+                    arg: *arg,
+                };
+                hex::decode_to_slice(lookup, &mut rule.lookup)
+                    .map_err(|_| eyre::eyre!("Invalid lookup selector {}", lookup))?;
+                contract_rules.insert(selector.clone(), rule);
+            }
+        }
+        Ok(OwnershipPolicy {
+            sequencer_url: sequencer_url.to_string(),
+            rules,
+            cache: NonZeroUsize::new(config.max_entries)
+                .map(|size| Mutex::new(LruCache::new(size))),
+            ttl: Duration::from_millis(config.ttl_ms),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub fn create_client(&self) -> Client<L2> {
+        let url = SensitiveUrl::from_str(&self.sequencer_url)
+            .unwrap_or_else(|_| panic!("Unable to parse client URL: {}", &self.sequencer_url));
+        Client::http(url)
+            .unwrap_or_else(|_| {
+                panic!("Unable to create a client for fork: {}", self.sequencer_url)
+            })
+            .build()
+    }
+
+    fn cached(&self, key: &LookupKey) -> Option<Option<Address>> {
+        let mut cache = self.cache.as_ref()?.lock().unwrap();
+        match cache.get(key) {
+            Some((owner, expires_at)) if *expires_at > Instant::now() => Some(*owner),
+            _ => None,
+        }
+    }
+
+    // Owner returned by the lookup - None if the result isn't an address (e.g. burned token).
+    async fn owner(&self, key: LookupKey) -> Result<Option<Address>, Denial> {
+        if let Some(owner) = self.cached(&key) {
+            return Ok(owner);
+        }
+        let (contract, selector, arg) = key;
+        let mut req = CallRequest::default();
+        req.to = Some(contract);
+        req.data = Some(Bytes([&selector[..], &arg[..]].concat()));
+        let client = self.create_client();
+        let result = observe_upstream("eth_call", client.call(req, None))
+            .await
+            .map_err(|err| {
+                tracing::warn!(contract = ?contract, "Ownership lookup failed: {}", err);
+                Denial::OwnerLookupFailed { contract }
+            })?;
+
+        let owner = result
+            .0
+            .get(..32)
+            .filter(|word| word[..12].iter().all(|byte| *byte == 0))
+            .map(|word| Address::from_slice(&word[12..]));
+        if let Some(cache) = &self.cache {
+            cache
+                .lock()
+                .unwrap()
+                .put(key, (owner, Instant::now() + self.ttl));
+        }
+        Ok(owner)
+    }
+}
+
+#[async_trait]
+impl Policy for OwnershipPolicy {
+    async fn evaluate_call(&self, req: &CallRequest, users: Option<&HashSet<Address>>) -> Decision {
+        let (Some(contract), Some(data), Some(users)) = (req.to, req.data.as_ref(), users) else {
+            return Decision::Abstain;
+        };
+        let Some(selector) = data.0.get(..4) else {
+            return Decision::Abstain;
+        };
+        let Some(rule) = self
+            .rules
+            .get(&contract)
+            .and_then(|rules| rules.get(&hex::encode(selector)))
+        else {
+            return Decision::Abstain;
+        };
+        let Some(arg) = rule
+            .arg
+            .checked_mul(32)
+            .and_then(|offset| offset.checked_add(4))
+            .and_then(|start| data.0.get(start..start.checked_add(32)?))
+        else {
+            return Decision::Abstain;
+        };
+
+        match self
+            .owner((contract, rule.lookup, arg.try_into().unwrap()))
+            .await
+        {
+            Ok(Some(owner)) if users.contains(&owner) => Decision::Allow,
+            // The owner is not revealed - on a private chain it is not public.
+            Ok(_) => Decision::Deny(Denial::NotTokenOwner { contract }),
+            Err(denial) => Decision::Deny(denial),
+        }
+    }
+}
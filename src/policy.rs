@@ -0,0 +1,234 @@
+// Authorization decisions, consulted by the proxy for every request.
+//
+// The whitelist from the config is one `Policy`. Bespoke rules can be added by implementing
+// the trait and combining it with the whitelist (see `AllOf`, `AnyOf` and `DenyOverrides`).
+
+use std::{
+    collections::HashSet,
+    ops::ControlFlow,
+    sync::{Arc, RwLock},
+};
+
+use zksync_types::{api::Log, transaction_request::CallRequest, web3::Bytes, Address};
+use zksync_web3_decl::jsonrpsee::core::async_trait;
+
+use crate::error::Denial;
+
+/// Outcome of a single policy check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    Allow,
+    Deny(Denial),
+    /// The policy has no opinion (e.g. a rule for a different contract).
+    Abstain,
+}
+
+impl Decision {
+    pub fn is_allowed(&self) -> bool {
+        matches!(self, Decision::Allow)
+    }
+
+    /// Requests that no policy allowed are denied.
+    pub fn into_result(self) -> Result<(), Denial> {
+        match self {
+            Decision::Allow => Ok(()),
+            Decision::Deny(denial) => Err(denial),
+            Decision::Abstain => Err(Denial::NotAllowedByPolicy),
+        }
+    }
+}
+
+impl From<Result<(), Denial>> for Decision {
+    fn from(result: Result<(), Denial>) -> Self {
+        match result {
+            Ok(()) => Decision::Allow,
+            Err(denial) => Decision::Deny(denial),
+        }
+    }
+}
+
+/// Authorization rules. `users` are the addresses bound to the caller's credential
+/// (None for requests without credentials).
+///
+/// Every check abstains by default, so a policy only has to implement the ones it cares about.
+/// Calls are evaluated asynchronously, so that rules can look things up upstream.
+#[async_trait]
+pub trait Policy: Send + Sync {
+    /// 'eth_call' (and gas / fee estimation) of a contract.
+    async fn evaluate_call(
+        &self,
+        _req: &CallRequest,
+        _users: Option<&HashSet<Address>>,
+    ) -> Decision {
+        Decision::Abstain
+    }
+
+    /// Raw (signed) transaction sent with eth_sendRawTransaction.
+    fn evaluate_tx(&self, _tx: &Bytes) -> Decision {
+        Decision::Abstain
+    }
+
+    /// Log returned by filters and subscriptions.
+    fn evaluate_log(&self, _log: &Log, _users: Option<&HashSet<Address>>) -> Decision {
+        Decision::Abstain
+    }
+
+    /// Whole RPC method (public name, like 'eth_getBalance' - also for the private versions).
+    fn evaluate_method(
+        &self,
+        _method: &'static str,
+        _users: Option<&HashSet<Address>>,
+    ) -> Decision {
+        Decision::Abstain
+    }
+}
+
+/// Allowed only if every policy allows it. Otherwise the first denial (or abstains).
+pub struct AllOf(pub Vec<Arc<dyn Policy>>);
+
+/// Allowed if any policy allows it. Otherwise the first denial (or abstains).
+pub struct AnyOf(pub Vec<Arc<dyn Policy>>);
+
+/// Denied if any policy denies it, allowed if any other allows it. Policies that abstain
+/// don't count - unlike in `AllOf`.
+pub struct DenyOverrides(pub Vec<Arc<dyn Policy>>);
+
+// How a combinator merges the decisions of its policies (in order). `Break` skips
+// the remaining policies.
+trait Combine {
+    const INITIAL: Decision;
+
+    fn step(result: Decision, next: Decision) -> ControlFlow<Decision, Decision>;
+
+    fn combine(
+        policies: &[Arc<dyn Policy>],
+        evaluate: impl Fn(&dyn Policy) -> Decision,
+    ) -> Decision {
+        let mut result = Self::INITIAL;
+        for policy in policies {
+            match Self::step(result, evaluate(policy.as_ref())) {
+                ControlFlow::Break(decision) => return decision,
+                ControlFlow::Continue(decision) => result = decision,
+            }
+        }
+        result
+    }
+}
+
+impl Combine for AllOf {
+    const INITIAL: Decision = Decision::Allow;
+
+    fn step(result: Decision, next: Decision) -> ControlFlow<Decision, Decision> {
+        match next {
+            Decision::Allow => ControlFlow::Continue(result),
+            Decision::Deny(denial) => ControlFlow::Break(Decision::Deny(denial)),
+            Decision::Abstain => ControlFlow::Continue(Decision::Abstain),
+        }
+    }
+}
+
+impl Combine for AnyOf {
+    const INITIAL: Decision = Decision::Abstain;
+
+    fn step(result: Decision, next: Decision) -> ControlFlow<Decision, Decision> {
+        match (result, next) {
+            (_, Decision::Allow) => ControlFlow::Break(Decision::Allow),
+            (Decision::Abstain, next) => ControlFlow::Continue(next),
+            (result, _) => ControlFlow::Continue(result),
+        }
+    }
+}
+
+impl Combine for DenyOverrides {
+    const INITIAL: Decision = Decision::Abstain;
+
+    fn step(result: Decision, next: Decision) -> ControlFlow<Decision, Decision> {
+        match next {
+            Decision::Allow => ControlFlow::Continue(Decision::Allow),
+            Decision::Deny(denial) => ControlFlow::Break(Decision::Deny(denial)),
+            Decision::Abstain => ControlFlow::Continue(result),
+        }
+    }
+}
+
+macro_rules! impl_combinator {
+    ($combinator:ty) => {
+        #[async_trait]
+        impl Policy for $combinator {
+            async fn evaluate_call(
+                &self,
+                req: &CallRequest,
+                users: Option<&HashSet<Address>>,
+            ) -> Decision {
+                let mut result = <$combinator>::INITIAL;
+                for policy in &self.0 {
+                    let next = policy.evaluate_call(req, users).await;
+                    match <$combinator>::step(result, next) {
+                        ControlFlow::Break(decision) => return decision,
+                        ControlFlow::Continue(decision) => result = decision,
+                    }
+                }
+                result
+            }
+
+            fn evaluate_tx(&self, tx: &Bytes) -> Decision {
+                <$combinator>::combine(&self.0, |policy| policy.evaluate_tx(tx))
+            }
+
+            fn evaluate_log(&self, log: &Log, users: Option<&HashSet<Address>>) -> Decision {
+                <$combinator>::combine(&self.0, |policy| policy.evaluate_log(log, users))
+            }
+
+            fn evaluate_method(
+                &self,
+                method: &'static str,
+                users: Option<&HashSet<Address>>,
+            ) -> Decision {
+                <$combinator>::combine(&self.0, |policy| policy.evaluate_method(method, users))
+            }
+        }
+    };
+}
+
+impl_combinator!(AllOf);
+impl_combinator!(AnyOf);
+impl_combinator!(DenyOverrides);
+
+/// Policy that can be replaced while the proxy runs (e.g. when the whitelist is changed
+/// through the admin namespace).
+pub struct SwappablePolicy(RwLock<Arc<dyn Policy>>);
+
+impl SwappablePolicy {
+    pub fn new(policy: Arc<dyn Policy>) -> Self {
+        SwappablePolicy(RwLock::new(policy))
+    }
+
+    pub fn get(&self) -> Arc<dyn Policy> {
+        self.0.read().unwrap().clone()
+    }
+
+    pub fn set(&self, policy: Arc<dyn Policy>) {
+        *self.0.write().unwrap() = policy;
+    }
+}
+
+#[async_trait]
+impl Policy for SwappablePolicy {
+    async fn evaluate_call(&self, req: &CallRequest, users: Option<&HashSet<Address>>) -> Decision {
+        // Requests that already started finish with the old policy.
+        let policy = self.get();
+        policy.evaluate_call(req, users).await
+    }
+
+    fn evaluate_tx(&self, tx: &Bytes) -> Decision {
+        self.get().evaluate_tx(tx)
+    }
+
+    fn evaluate_log(&self, log: &Log, users: Option<&HashSet<Address>>) -> Decision {
+        self.get().evaluate_log(log, users)
+    }
+
+    fn evaluate_method(&self, method: &'static str, users: Option<&HashSet<Address>>) -> Decision {
+        self.get().evaluate_method(method, users)
+    }
+}
@@ -1,36 +1,61 @@
+use serde_json::json;
 use std::{
     collections::{hash_map::Entry, HashMap, HashSet},
     str::FromStr,
-    sync::Mutex,
+    sync::{Arc, Mutex},
 };
 use zksync_types::{
     api::{
-        BlockId, BlockIdVariant, BlockNumber, Transaction, TransactionReceipt, TransactionVariant,
+        BlockDetails, BlockId, BlockIdVariant, BlockNumber, BridgeAddresses, L1BatchDetails,
+        L2ToL1LogProof, Proof, ProtocolVersion, Transaction, TransactionDetailedResult,
+        TransactionDetails, TransactionReceipt, TransactionVariant,
     },
+    fee::Fee,
+    fee_model::{FeeParams, PubdataIndependentBatchFeeModelInput},
     transaction_request::CallRequest,
     url::SensitiveUrl,
     web3::{Bytes, FeeHistory, Index, SyncState},
-    Address, H256, U256, U64,
+    Address, L1BatchNumber, L2BlockNumber, H256, U256, U64,
 };
 use zksync_web3_decl::jsonrpsee::proc_macros::rpc;
 use zksync_web3_decl::{
     client::{Client, L2},
     jsonrpsee::{
         core::{async_trait, RpcResult},
-        types::{error::ErrorCode, ErrorObject},
+        types::{error::ErrorCode, ErrorObject, ErrorObjectOwned},
+        RpcModule,
     },
-    namespaces::{EthNamespaceClient, EthNamespaceServer},
-    types::{Block, Filter, FilterChanges, Log},
+    namespaces::{
+        EthNamespaceClient, EthNamespaceServer, Web3NamespaceServer, ZksNamespaceClient,
+        ZksNamespaceServer,
+    },
+    types::{Block, Filter, FilterChanges, Log, Token},
 };
 
 use zksync_web3_decl::*;
 
-use crate::whitelist::ContractWhitelist;
+use crate::audit::{AuditEvent, AuditLog};
+use crate::cache::{CachePolicy, ResponseCache};
+use crate::error::{upstream_call_error, upstream_error, Denial};
+use crate::estimation::EstimationConfig;
+use crate::filters::{FilterKind, FilterRegistry};
+use crate::metrics::{self, observe_upstream};
+use crate::policy::Policy;
+use crate::redaction::BlockRedaction;
+use crate::response_filter::ResponseFilters;
+use crate::rewrite::PRIVATE_METHODS;
 
 #[derive(Clone)]
 pub struct Proxy {
     pub sequencer_url: String,
-    pub whitelist: ContractWhitelist,
+    pub policy: Arc<dyn Policy>,
+    pub response_filters: Arc<ResponseFilters>,
+    pub estimation: EstimationConfig,
+    pub cache: Arc<ResponseCache>,
+    pub filters: Arc<FilterRegistry>,
+    pub redaction: BlockRedaction,
+    // Whether denials should explain which rule rejected the request.
+    pub verbose_errors: bool,
 }
 
 impl Proxy {
@@ -45,16 +70,53 @@ impl Proxy {
     }
 
     // Whether to allow this 'call' request to go through.
-    pub fn allow_unauthorized_call(&self, req: &CallRequest) -> bool {
-        self.whitelist.allow_unauthorized_call(req)
+    pub async fn allow_unauthorized_call(&self, req: &CallRequest) -> Result<(), Denial> {
+        let result = self.policy.evaluate_call(req, None).await.into_result();
+        metrics::record_whitelist_decision("unauthorized", &result);
+        result
+    }
+
+    // Whether the method can be called without credentials.
+    fn allow_method(&self, method: &'static str) -> RpcResult<()> {
+        self.policy
+            .evaluate_method(method, None)
+            .into_result()
+            .map_err(|denial| self.deny(denial))
+    }
+
+    fn allow_tx(&self, tx: &Bytes) -> RpcResult<()> {
+        self.policy
+            .evaluate_tx(tx)
+            .into_result()
+            .map_err(|denial| self.deny(denial))
+    }
+
+    fn deny(&self, denial: Denial) -> ErrorObjectOwned {
+        metrics::record_denial(&denial);
+        denial.into_error(self.verbose_errors)
+    }
+
+    fn method_not_allowed(&self, method: &'static str) -> ErrorObjectOwned {
+        self.deny(Denial::MethodNotAllowed { method })
     }
 }
 
+/// Addresses bound to each credential - shared with the websocket handshake and subscriptions.
+pub type CredentialStore = Arc<Mutex<HashMap<String, HashSet<Address>>>>;
+
 pub struct PrivateProxy {
     pub sequencer_url: String,
-    pub whitelist: ContractWhitelist,
+    pub policy: Arc<dyn Policy>,
+    pub response_filters: Arc<ResponseFilters>,
+    pub estimation: EstimationConfig,
+    // Whether denials should explain which rule rejected the request.
+    pub verbose_errors: bool,
+    pub audit: Arc<AuditLog>,
+    // Shared with the public proxy - ids are global, but each filter belongs to one credential.
+    pub filters: Arc<FilterRegistry>,
+    pub redaction: BlockRedaction,
 
-    pub credentials: Mutex<HashMap<String, HashSet<Address>>>,
+    pub credentials: CredentialStore,
 }
 
 impl PrivateProxy {
@@ -67,16 +129,90 @@ impl PrivateProxy {
             })
             .build()
     }
-    pub fn allow_authorized_call(&self, credentials: &String, req: &CallRequest) -> bool {
-        let allowed_users = {
-            let credentials = credentials.strip_suffix(":").unwrap_or(&credentials);
-            let data = self.credentials.lock().unwrap();
-            match data.get(credentials) {
-                Some(credentials) => credentials.clone(),
-                None => return false,
+    fn deny(&self, denial: Denial) -> ErrorObjectOwned {
+        metrics::record_denial(&denial);
+        denial.into_error(self.verbose_errors)
+    }
+
+    // Returns the addresses that given credentials are authorized for - if they may use the
+    // method (given by its public name).
+    fn authorized_addresses(
+        &self,
+        method: &'static str,
+        credentials: &str,
+    ) -> Result<HashSet<Address>, Denial> {
+        let users = authorized_addresses(&self.credentials, credentials)?;
+        self.policy
+            .evaluate_method(method, Some(&users))
+            .into_result()?;
+        Ok(users)
+    }
+
+    // Returns the addresses of the credentials, if the call is allowed.
+    pub async fn allow_authorized_call(
+        &self,
+        method: &'static str,
+        credentials: &str,
+        req: &CallRequest,
+    ) -> Result<HashSet<Address>, Denial> {
+        let credentials = normalize_credentials(credentials);
+        let (allowed_users, result) = match self.authorized_addresses(method, credentials) {
+            Ok(allowed_users) => {
+                let result = self
+                    .policy
+                    .evaluate_call(req, Some(&allowed_users))
+                    .await
+                    .into_result();
+                (allowed_users, result)
             }
+            Err(denial) => (HashSet::new(), Err(denial)),
         };
-        self.whitelist.allow_authorized_call(req, &allowed_users)
+        metrics::record_whitelist_decision("authorized", &result);
+        self.audit.record(
+            AuditEvent {
+                event: "call",
+                credentials,
+                addresses: allowed_users.iter().copied().collect(),
+                contract: req.to,
+                selector: req
+                    .data
+                    .as_ref()
+                    .and_then(|data| data.0.get(..4))
+                    .map(hex::encode),
+            },
+            &result,
+        );
+        result.map(|()| allowed_users)
+    }
+
+    fn allow_address(
+        &self,
+        event: &str,
+        method: &'static str,
+        credentials: &str,
+        address: &Address,
+    ) -> Result<(), Denial> {
+        let credentials = normalize_credentials(credentials);
+        let result = self
+            .authorized_addresses(method, credentials)
+            .and_then(|allowed_users| {
+                if allowed_users.contains(address) {
+                    Ok(())
+                } else {
+                    Err(Denial::AddressNotAuthorized { address: *address })
+                }
+            });
+        self.audit.record(
+            AuditEvent {
+                event,
+                credentials,
+                addresses: vec![*address],
+                contract: None,
+                selector: None,
+            },
+            &result,
+        );
+        result
     }
 
     fn authorize_credential(
@@ -86,6 +222,17 @@ impl PrivateProxy {
         signature: &String,
     ) -> bool {
         // TODO: verify signature.
+        let credentials = normalize_credentials(&credentials).to_string();
+        self.audit.record(
+            AuditEvent {
+                event: "add_credential",
+                credentials: &credentials,
+                addresses: vec![address],
+                contract: None,
+                selector: None,
+            },
+            &Ok(()),
+        );
         let mut data = self.credentials.lock().unwrap();
         match data.entry(credentials) {
             Entry::Occupied(mut users) => users.get_mut().insert(address),
@@ -94,10 +241,12 @@ impl PrivateProxy {
                 true
             }
         };
+        metrics::set_active_credentials(data.len());
         true
     }
 
-    fn check_credential_internal(&self, credentials: &String, address: &Address) -> bool {
+    fn check_credential_internal(&self, credentials: &str, address: &Address) -> bool {
+        let credentials = normalize_credentials(credentials);
         let data = self.credentials.lock().unwrap();
 
         data.get(credentials)
@@ -106,6 +255,81 @@ impl PrivateProxy {
     }
 }
 
+/// Returns the addresses that given credentials are authorized for.
+pub fn authorized_addresses(
+    store: &CredentialStore,
+    credentials: &str,
+) -> Result<HashSet<Address>, Denial> {
+    let credentials = normalize_credentials(credentials);
+    if credentials.is_empty() {
+        return Err(Denial::MissingCredential);
+    }
+    let data = store.lock().unwrap();
+    data.get(credentials)
+        .cloned()
+        .ok_or(Denial::UnknownCredential)
+}
+
+// Leaves only the transactions sent by (or to) one of the users - as hashes, unless
+// `full_transactions` is set. The block must have been fetched with full transactions.
+fn only_own_transactions(
+    mut block: Block<TransactionVariant>,
+    users: &HashSet<Address>,
+    full_transactions: bool,
+) -> Block<TransactionVariant> {
+    let is_own = |tx: &Transaction| {
+        tx.from.map_or(false, |from| users.contains(&from))
+            || tx.to.map_or(false, |to| users.contains(&to))
+    };
+    block.transactions = block
+        .transactions
+        .into_iter()
+        .filter_map(|tx| match tx {
+            TransactionVariant::Full(tx) if is_own(&tx) => Some(if full_transactions {
+                TransactionVariant::Full(tx)
+            } else {
+                TransactionVariant::Hash(tx.hash)
+            }),
+            _ => None,
+        })
+        .collect();
+    block
+}
+
+/// Credentials as they are stored, looked up and audited. Basic auth with an empty password
+/// leaves a trailing ':'.
+pub fn normalize_credentials(credentials: &str) -> &str {
+    credentials.strip_suffix(':').unwrap_or(credentials)
+}
+
+/// Methods of the private namespace that manage credentials - they have no public version.
+pub const CREDENTIAL_METHODS: &[&str] = &["privateeth_addCredential", "privateeth_checkCredential"];
+
+/// Checks that `PRIVATE_METHODS` matches the methods served by the private namespace.
+pub fn validate_private_methods<'a>(
+    private_method_names: impl Iterator<Item = &'a str>,
+) -> eyre::Result<()> {
+    let served: HashSet<&str> = private_method_names.collect();
+    for (public, private) in PRIVATE_METHODS {
+        if !served.contains(private) {
+            eyre::bail!(
+                "{} is rewritten to {}, which is not served",
+                public,
+                private
+            );
+        }
+    }
+    for method in served {
+        let rewritten = PRIVATE_METHODS
+            .iter()
+            .any(|(_, private)| *private == method);
+        if !rewritten && !CREDENTIAL_METHODS.contains(&method) {
+            eyre::bail!("{} is missing from PRIVATE_METHODS", method);
+        }
+    }
+    Ok(())
+}
+
 #[rpc(server, client, namespace = "privateeth")]
 pub trait PrivateEthNamespace {
     #[method(name = "blockNumber")]
@@ -124,6 +348,67 @@ pub trait PrivateEthNamespace {
         req: CallRequest,
         block: Option<BlockIdVariant>,
     ) -> RpcResult<Bytes>;
+    #[method(name = "estimateGas")]
+    async fn private_estimate_gas(
+        &self,
+        credentials: String,
+        req: CallRequest,
+        block: Option<BlockNumber>,
+    ) -> RpcResult<U256>;
+    #[method(name = "getTransactionCount")]
+    async fn private_get_transaction_count(
+        &self,
+        credentials: String,
+        address: Address,
+        block: Option<BlockIdVariant>,
+    ) -> RpcResult<U256>;
+    #[method(name = "getStorageAt")]
+    async fn private_get_storage_at(
+        &self,
+        credentials: String,
+        address: Address,
+        idx: U256,
+        block: Option<BlockIdVariant>,
+    ) -> RpcResult<H256>;
+    #[method(name = "getBlockByNumber")]
+    async fn private_get_block_by_number(
+        &self,
+        credentials: String,
+        block_number: BlockNumber,
+        full_transactions: bool,
+    ) -> RpcResult<Option<Block<TransactionVariant>>>;
+    #[method(name = "getBlockByHash")]
+    async fn private_get_block_by_hash(
+        &self,
+        credentials: String,
+        hash: H256,
+        full_transactions: bool,
+    ) -> RpcResult<Option<Block<TransactionVariant>>>;
+    #[method(name = "estimateFee")]
+    async fn private_estimate_fee(&self, credentials: String, req: CallRequest) -> RpcResult<Fee>;
+    #[method(name = "getAllAccountBalances")]
+    async fn private_get_all_account_balances(
+        &self,
+        credentials: String,
+        address: Address,
+    ) -> RpcResult<HashMap<Address, U256>>;
+
+    #[method(name = "newFilter")]
+    async fn private_new_filter(&self, credentials: String, filter: Filter) -> RpcResult<U256>;
+    #[method(name = "newBlockFilter")]
+    async fn private_new_block_filter(&self, credentials: String) -> RpcResult<U256>;
+    #[method(name = "getFilterChanges")]
+    async fn private_get_filter_changes(
+        &self,
+        credentials: String,
+        filter_index: U256,
+    ) -> RpcResult<FilterChanges>;
+    #[method(name = "uninstallFilter")]
+    async fn private_uninstall_filter(
+        &self,
+        credentials: String,
+        filter_index: U256,
+    ) -> RpcResult<bool>;
 
     #[method(name = "addCredential")]
     async fn add_credential(
@@ -139,9 +424,71 @@ pub trait PrivateEthNamespace {
 
 #[async_trait]
 impl PrivateEthNamespaceServer for PrivateProxy {
+    // Also validates the credentials - so it can be used as a cheap session check.
     async fn private_get_block_number(&self, credentials: String) -> RpcResult<U64> {
-        println!("credentials: {:?} ", credentials);
-        Ok(42.into())
+        self.authorized_addresses("eth_blockNumber", &credentials)
+            .map_err(|denial| self.deny(denial))?;
+        let client = self.create_client();
+        observe_upstream("eth_blockNumber", client.get_block_number())
+            .await
+            .map_err(upstream_error)
+    }
+
+    async fn private_new_filter(&self, credentials: String, filter: Filter) -> RpcResult<U256> {
+        self.authorized_addresses("eth_newFilter", &credentials)
+            .map_err(|denial| self.deny(denial))?;
+        let client = self.create_client();
+        self.filters
+            .install(
+                &client,
+                Some(normalize_credentials(&credentials)),
+                FilterKind::Logs(filter),
+            )
+            .await
+    }
+
+    async fn private_new_block_filter(&self, credentials: String) -> RpcResult<U256> {
+        self.authorized_addresses("eth_newBlockFilter", &credentials)
+            .map_err(|denial| self.deny(denial))?;
+        let client = self.create_client();
+        self.filters
+            .install(
+                &client,
+                Some(normalize_credentials(&credentials)),
+                FilterKind::Blocks,
+            )
+            .await
+    }
+
+    async fn private_get_filter_changes(
+        &self,
+        credentials: String,
+        filter_index: U256,
+    ) -> RpcResult<FilterChanges> {
+        // Addresses are checked on every poll, so revoking them takes effect immediately.
+        let users = self
+            .authorized_addresses("eth_getFilterChanges", &credentials)
+            .map_err(|denial| self.deny(denial))?;
+        let client = self.create_client();
+        self.filters
+            .changes(
+                &client,
+                self.policy.as_ref(),
+                Some(normalize_credentials(&credentials)),
+                Some(&users),
+                filter_index,
+            )
+            .await
+    }
+
+    async fn private_uninstall_filter(
+        &self,
+        credentials: String,
+        filter_index: U256,
+    ) -> RpcResult<bool> {
+        Ok(self
+            .filters
+            .uninstall(Some(normalize_credentials(&credentials)), filter_index))
     }
 
     async fn add_credential(
@@ -168,14 +515,12 @@ impl PrivateEthNamespaceServer for PrivateProxy {
         address: Address,
         block: Option<BlockIdVariant>,
     ) -> RpcResult<U256> {
+        self.allow_address("get_balance", "eth_getBalance", &credentials, &address)
+            .map_err(|denial| self.deny(denial))?;
         let client = self.create_client();
-        if !self.check_credential_internal(&credentials, &address) {
-            return Err(ErrorObject::from(ErrorCode::ServerError(403)));
-        }
-        client
-            .get_balance(address, block)
+        observe_upstream("eth_getBalance", client.get_balance(address, block))
             .await
-            .map_err(|_| ErrorObject::from(ErrorCode::InternalError).into_owned())
+            .map_err(upstream_error)
     }
 
     async fn private_call(
@@ -184,90 +529,261 @@ impl PrivateEthNamespaceServer for PrivateProxy {
         req: CallRequest,
         block: Option<BlockIdVariant>,
     ) -> RpcResult<Bytes> {
-        if !self.allow_authorized_call(&credentials, &req) {
-            return Err(ErrorObject::from(ErrorCode::ServerError(403)));
-        }
+        let users = self
+            .allow_authorized_call("eth_call", &credentials, &req)
+            .await
+            .map_err(|denial| self.deny(denial))?;
+        let client = self.create_client();
+        let result = observe_upstream("eth_call", client.call(req.clone(), block))
+            .await
+            .map_err(upstream_call_error)?;
+        self.response_filters
+            .apply(&req, Some(&users), result)
+            .map_err(|denial| self.deny(denial))
+    }
+
+    async fn private_estimate_gas(
+        &self,
+        credentials: String,
+        req: CallRequest,
+        block: Option<BlockNumber>,
+    ) -> RpcResult<U256> {
+        let users = self
+            .allow_authorized_call("eth_estimateGas", &credentials, &req)
+            .await
+            .map_err(|denial| self.deny(denial))?;
+        self.estimation
+            .allow_authorized(&req, &users)
+            .map_err(|denial| self.deny(denial))?;
+        let client = self.create_client();
+        observe_upstream("eth_estimateGas", client.estimate_gas(req, block))
+            .await
+            .map_err(upstream_call_error)
+    }
+
+    async fn private_get_transaction_count(
+        &self,
+        credentials: String,
+        address: Address,
+        block: Option<BlockIdVariant>,
+    ) -> RpcResult<U256> {
+        self.allow_address(
+            "get_transaction_count",
+            "eth_getTransactionCount",
+            &credentials,
+            &address,
+        )
+        .map_err(|denial| self.deny(denial))?;
+        let client = self.create_client();
+        observe_upstream(
+            "eth_getTransactionCount",
+            client.get_transaction_count(address, block),
+        )
+        .await
+        .map_err(upstream_error)
+    }
+
+    // Only storage of the authorized addresses themselves (e.g. smart accounts).
+    async fn private_get_storage_at(
+        &self,
+        credentials: String,
+        address: Address,
+        idx: U256,
+        block: Option<BlockIdVariant>,
+    ) -> RpcResult<H256> {
+        self.allow_address("get_storage_at", "eth_getStorageAt", &credentials, &address)
+            .map_err(|denial| self.deny(denial))?;
         let client = self.create_client();
-        client
-            .call(req, block)
+        observe_upstream(
+            "eth_getStorageAt",
+            client.get_storage_at(address, idx, block),
+        )
+        .await
+        .map_err(upstream_error)
+    }
+
+    async fn private_get_block_by_number(
+        &self,
+        credentials: String,
+        block_number: BlockNumber,
+        full_transactions: bool,
+    ) -> RpcResult<Option<Block<TransactionVariant>>> {
+        let users = self
+            .authorized_addresses("eth_getBlockByNumber", &credentials)
+            .map_err(|denial| self.deny(denial))?;
+        let client = self.create_client();
+        // Always fetched in full - senders and recipients are needed to pick the user's own.
+        let block = observe_upstream(
+            "eth_getBlockByNumber",
+            client.get_block_by_number(block_number, true),
+        )
+        .await
+        .map_err(upstream_error)?;
+        Ok(block.map(|block| {
+            let mut block = only_own_transactions(block, &users, full_transactions);
+            self.redaction.apply(&mut block);
+            block
+        }))
+    }
+
+    async fn private_get_block_by_hash(
+        &self,
+        credentials: String,
+        hash: H256,
+        full_transactions: bool,
+    ) -> RpcResult<Option<Block<TransactionVariant>>> {
+        let users = self
+            .authorized_addresses("eth_getBlockByHash", &credentials)
+            .map_err(|denial| self.deny(denial))?;
+        let client = self.create_client();
+        let block = observe_upstream("eth_getBlockByHash", client.get_block_by_hash(hash, true))
             .await
-            .map_err(|_| ErrorObject::from(ErrorCode::InternalError).into_owned())
+            .map_err(upstream_error)?;
+        Ok(block.map(|block| {
+            let mut block = only_own_transactions(block, &users, full_transactions);
+            self.redaction.apply(&mut block);
+            block
+        }))
+    }
+
+    async fn private_estimate_fee(&self, credentials: String, req: CallRequest) -> RpcResult<Fee> {
+        let users = self
+            .allow_authorized_call("zks_estimateFee", &credentials, &req)
+            .await
+            .map_err(|denial| self.deny(denial))?;
+        self.estimation
+            .allow_authorized(&req, &users)
+            .map_err(|denial| self.deny(denial))?;
+        let client = self.create_client();
+        observe_upstream("zks_estimateFee", client.estimate_fee(req))
+            .await
+            .map_err(upstream_call_error)
+    }
+
+    async fn private_get_all_account_balances(
+        &self,
+        credentials: String,
+        address: Address,
+    ) -> RpcResult<HashMap<Address, U256>> {
+        self.allow_address(
+            "get_all_account_balances",
+            "zks_getAllAccountBalances",
+            &credentials,
+            &address,
+        )
+        .map_err(|denial| self.deny(denial))?;
+        let client = self.create_client();
+        observe_upstream(
+            "zks_getAllAccountBalances",
+            client.get_all_account_balances(address),
+        )
+        .await
+        .map_err(upstream_error)
     }
 }
 
 #[async_trait]
 impl EthNamespaceServer for Proxy {
     async fn get_block_number(&self) -> RpcResult<U64> {
+        self.allow_method("eth_blockNumber")?;
         let client = self.create_client();
-        client
-            .get_block_number()
+        observe_upstream("eth_blockNumber", client.get_block_number())
             .await
-            .map_err(|_| ErrorObject::from(ErrorCode::InternalError).into_owned())
+            .map_err(upstream_error)
     }
 
+    // Not subject to the policy - the proxy itself needs it (for net_version). Fetched on the
+    // first call, so that the proxy can start before the sequencer.
     async fn chain_id(&self) -> RpcResult<U64> {
-        let client = self.create_client();
-        client
-            .chain_id()
+        self.cache
+            .get_or_fetch(
+                "eth_chainId",
+                json!([]),
+                CachePolicy::Forever,
+                || async move {
+                    let client = self.create_client();
+                    observe_upstream("eth_chainId", client.chain_id())
+                        .await
+                        .map_err(upstream_error)
+                },
+            )
             .await
-            .map_err(|_| ErrorObject::from(ErrorCode::InternalError).into_owned())
     }
 
     async fn call(&self, req: CallRequest, block: Option<BlockIdVariant>) -> RpcResult<Bytes> {
-        if !self.allow_unauthorized_call(&req) {
-            return Err(ErrorObject::from(ErrorCode::ServerError(403)));
-        }
+        self.allow_method("eth_call")?;
+        self.allow_unauthorized_call(&req)
+            .await
+            .map_err(|denial| self.deny(denial))?;
         let client = self.create_client();
-        client
-            .call(req, block)
+        let result = observe_upstream("eth_call", client.call(req.clone(), block))
             .await
-            .map_err(|_| ErrorObject::from(ErrorCode::InternalError).into_owned())
+            .map_err(upstream_call_error)?;
+        self.response_filters
+            .apply(&req, None, result)
+            .map_err(|denial| self.deny(denial))
     }
 
     async fn estimate_gas(&self, req: CallRequest, block: Option<BlockNumber>) -> RpcResult<U256> {
-        if !self.allow_unauthorized_call(&req) {
-            return Err(ErrorObject::from(ErrorCode::ServerError(403)));
-        }
+        self.allow_method("eth_estimateGas")?;
+        self.estimation
+            .allow_public("eth_estimateGas", &req)
+            .map_err(|denial| self.deny(denial))?;
+        self.allow_unauthorized_call(&req)
+            .await
+            .map_err(|denial| self.deny(denial))?;
         let client = self.create_client();
-        client
-            .estimate_gas(req, block)
+        observe_upstream("eth_estimateGas", client.estimate_gas(req, block))
             .await
-            .map_err(|_| ErrorObject::from(ErrorCode::InternalError).into_owned())
+            .map_err(|err| self.estimation.public_error(err))
     }
 
     async fn gas_price(&self) -> RpcResult<U256> {
+        self.allow_method("eth_gasPrice")?;
         let client = self.create_client();
-        client
-            .gas_price()
+        observe_upstream("eth_gasPrice", client.gas_price())
             .await
-            .map_err(|_| ErrorObject::from(ErrorCode::InternalError).into_owned())
+            .map_err(upstream_error)
     }
 
-    async fn new_filter(&self, _filter: Filter) -> RpcResult<U256> {
-        Err(ErrorObject::from(ErrorCode::ServerError(403)))
+    async fn new_filter(&self, filter: Filter) -> RpcResult<U256> {
+        self.allow_method("eth_newFilter")?;
+        let client = self.create_client();
+        self.filters
+            .install(&client, None, FilterKind::Logs(filter))
+            .await
     }
 
     async fn new_block_filter(&self) -> RpcResult<U256> {
-        Err(ErrorObject::from(ErrorCode::ServerError(403)))
+        self.allow_method("eth_newBlockFilter")?;
+        let client = self.create_client();
+        self.filters
+            .install(&client, None, FilterKind::Blocks)
+            .await
     }
 
-    async fn uninstall_filter(&self, _idx: U256) -> RpcResult<bool> {
-        Err(ErrorObject::from(ErrorCode::ServerError(403)))
+    async fn uninstall_filter(&self, idx: U256) -> RpcResult<bool> {
+        self.allow_method("eth_uninstallFilter")?;
+        Ok(self.filters.uninstall(None, idx))
     }
 
     async fn new_pending_transaction_filter(&self) -> RpcResult<U256> {
-        Err(ErrorObject::from(ErrorCode::ServerError(403)))
+        Err(self.method_not_allowed("eth_newPendingTransactionFilter"))
     }
 
     async fn get_logs(&self, _filter: Filter) -> RpcResult<Vec<Log>> {
-        Err(ErrorObject::from(ErrorCode::ServerError(403)))
+        Err(self.method_not_allowed("eth_getLogs"))
     }
 
     async fn get_filter_logs(&self, _filter_index: U256) -> RpcResult<FilterChanges> {
-        Err(ErrorObject::from(ErrorCode::ServerError(403)))
+        Err(self.method_not_allowed("eth_getFilterLogs"))
     }
-    async fn get_filter_changes(&self, _filter_index: U256) -> RpcResult<FilterChanges> {
-        Err(ErrorObject::from(ErrorCode::ServerError(403)))
+    async fn get_filter_changes(&self, filter_index: U256) -> RpcResult<FilterChanges> {
+        self.allow_method("eth_getFilterChanges")?;
+        let client = self.create_client();
+        self.filters
+            .changes(&client, self.policy.as_ref(), None, None, filter_index)
+            .await
     }
 
     async fn get_balance(
@@ -275,7 +791,7 @@ impl EthNamespaceServer for Proxy {
         _address: Address,
         _block: Option<BlockIdVariant>,
     ) -> RpcResult<U256> {
-        Err(ErrorObject::from(ErrorCode::ServerError(403)))
+        Err(self.method_not_allowed("eth_getBalance"))
     }
 
     async fn get_block_by_number(
@@ -283,19 +799,34 @@ impl EthNamespaceServer for Proxy {
         block_number: BlockNumber,
         full_transactions: bool,
     ) -> RpcResult<Option<Block<TransactionVariant>>> {
+        self.allow_method("eth_getBlockByNumber")?;
         if full_transactions {
-            return Err(ErrorObject::from(ErrorCode::ServerError(403)));
+            return Err(self.deny(Denial::FullTransactionsNotAllowed));
         }
-        let client = self.create_client();
-        let mut result = client
-            .get_block_by_number(block_number, full_transactions)
-            .await
-            .map_err(|_| ErrorObject::from(ErrorCode::InternalError).into_owned());
+        let policy = self.cache.block_data_policy(&block_number);
+        let mut result = self
+            .cache
+            .get_or_fetch(
+                "eth_getBlockByNumber",
+                json!([block_number, full_transactions]),
+                policy,
+                || async move {
+                    let client = self.create_client();
+                    observe_upstream(
+                        "eth_getBlockByNumber",
+                        client.get_block_by_number(block_number, full_transactions),
+                    )
+                    .await
+                    .map_err(upstream_error)
+                },
+            )
+            .await;
 
         // Filter out transactions
         if let Ok(result_details) = &mut result {
             if let Some(block) = result_details {
                 block.transactions.clear();
+                self.redaction.apply(block);
             }
         }
         result
@@ -306,19 +837,33 @@ impl EthNamespaceServer for Proxy {
         hash: H256,
         full_transactions: bool,
     ) -> RpcResult<Option<Block<TransactionVariant>>> {
+        self.allow_method("eth_getBlockByHash")?;
         if full_transactions {
-            return Err(ErrorObject::from(ErrorCode::ServerError(403)));
+            return Err(self.deny(Denial::FullTransactionsNotAllowed));
         }
-        let client = self.create_client();
-        let mut result = client
-            .get_block_by_hash(hash, full_transactions)
-            .await
-            .map_err(|_| ErrorObject::from(ErrorCode::InternalError).into_owned());
+        let mut result = self
+            .cache
+            .get_or_fetch(
+                "eth_getBlockByHash",
+                json!([hash, full_transactions]),
+                CachePolicy::UntilSealed,
+                || async move {
+                    let client = self.create_client();
+                    observe_upstream(
+                        "eth_getBlockByHash",
+                        client.get_block_by_hash(hash, full_transactions),
+                    )
+                    .await
+                    .map_err(upstream_error)
+                },
+            )
+            .await;
 
         // Filter out transactions
         if let Ok(result_details) = &mut result {
             if let Some(block) = result_details {
                 block.transactions.clear();
+                self.redaction.apply(block);
             }
         }
         result
@@ -328,37 +873,59 @@ impl EthNamespaceServer for Proxy {
         &self,
         block_number: BlockNumber,
     ) -> RpcResult<Option<U256>> {
+        if self.redaction.hide_transaction_counts {
+            return Err(self.method_not_allowed("eth_getBlockTransactionCountByNumber"));
+        }
+        self.allow_method("eth_getBlockTransactionCountByNumber")?;
         let client = self.create_client();
-        client
-            .get_block_transaction_count_by_number(block_number)
-            .await
-            .map_err(|_| ErrorObject::from(ErrorCode::InternalError).into_owned())
+        observe_upstream(
+            "eth_getBlockTransactionCountByNumber",
+            client.get_block_transaction_count_by_number(block_number),
+        )
+        .await
+        .map_err(upstream_error)
     }
 
     async fn get_block_receipts(
         &self,
         block_id: BlockId,
     ) -> RpcResult<Option<Vec<TransactionReceipt>>> {
-        Err(ErrorObject::from(ErrorCode::ServerError(403)))
+        Err(self.method_not_allowed("eth_getBlockReceipts"))
     }
 
     async fn get_block_transaction_count_by_hash(
         &self,
         block_hash: H256,
     ) -> RpcResult<Option<U256>> {
+        if self.redaction.hide_transaction_counts {
+            return Err(self.method_not_allowed("eth_getBlockTransactionCountByHash"));
+        }
+        self.allow_method("eth_getBlockTransactionCountByHash")?;
         let client = self.create_client();
-        client
-            .get_block_transaction_count_by_hash(block_hash)
-            .await
-            .map_err(|_| ErrorObject::from(ErrorCode::InternalError).into_owned())
+        observe_upstream(
+            "eth_getBlockTransactionCountByHash",
+            client.get_block_transaction_count_by_hash(block_hash),
+        )
+        .await
+        .map_err(upstream_error)
     }
 
     async fn get_code(&self, address: Address, block: Option<BlockIdVariant>) -> RpcResult<Bytes> {
-        let client = self.create_client();
-        client
-            .get_code(address, block)
+        self.allow_method("eth_getCode")?;
+        let policy = self.cache.block_policy(&block);
+        self.cache
+            .get_or_fetch(
+                "eth_getCode",
+                json!([address, block]),
+                policy,
+                || async move {
+                    let client = self.create_client();
+                    observe_upstream("eth_getCode", client.get_code(address, block))
+                        .await
+                        .map_err(upstream_error)
+                },
+            )
             .await
-            .map_err(|_| ErrorObject::from(ErrorCode::InternalError).into_owned())
     }
 
     // Storage is hard one.. for now not allowed.
@@ -368,28 +935,28 @@ impl EthNamespaceServer for Proxy {
         _idx: U256,
         _block: Option<BlockIdVariant>,
     ) -> RpcResult<H256> {
-        Err(ErrorObject::from(ErrorCode::ServerError(403)))
+        Err(self.method_not_allowed("eth_getStorageAt"))
     }
 
-    // Transaction count is ok.
+    // Transaction count reveals account activity - available only through
+    // privateeth_getTransactionCount.
     async fn get_transaction_count(
         &self,
-        address: Address,
-        block: Option<BlockIdVariant>,
+        _address: Address,
+        _block: Option<BlockIdVariant>,
     ) -> RpcResult<U256> {
-        let client = self.create_client();
-        client
-            .get_transaction_count(address, block)
-            .await
-            .map_err(|_| ErrorObject::from(ErrorCode::InternalError).into_owned())
+        Err(self.method_not_allowed("eth_getTransactionCount"))
     }
 
     async fn get_transaction_by_hash(&self, hash: H256) -> RpcResult<Option<Transaction>> {
+        self.allow_method("eth_getTransactionByHash")?;
         let client = self.create_client();
-        client
-            .get_transaction_by_hash(hash)
-            .await
-            .map_err(|_| ErrorObject::from(ErrorCode::InternalError).into_owned())
+        observe_upstream(
+            "eth_getTransactionByHash",
+            client.get_transaction_by_hash(hash),
+        )
+        .await
+        .map_err(upstream_error)
     }
 
     // Listing block transactions is not allowed.
@@ -398,7 +965,7 @@ impl EthNamespaceServer for Proxy {
         _block_hash: H256,
         _index: Index,
     ) -> RpcResult<Option<Transaction>> {
-        Err(ErrorObject::from(ErrorCode::ServerError(403)))
+        Err(self.method_not_allowed("eth_getTransactionByBlockHashAndIndex"))
     }
 
     // Listing block transactions is not allowed
@@ -407,92 +974,114 @@ impl EthNamespaceServer for Proxy {
         _block_number: BlockNumber,
         _index: Index,
     ) -> RpcResult<Option<Transaction>> {
-        Err(ErrorObject::from(ErrorCode::ServerError(403)))
+        Err(self.method_not_allowed("eth_getTransactionByBlockNumberAndIndex"))
     }
 
     // Tx receipt is allowed - if you know the hash.
+    // Only mined receipts are cached (cache skips 'null' results), and only sealed ones forever.
     async fn get_transaction_receipt(&self, hash: H256) -> RpcResult<Option<TransactionReceipt>> {
-        let client = self.create_client();
-        client
-            .get_transaction_receipt(hash)
+        self.allow_method("eth_getTransactionReceipt")?;
+        self.cache
+            .get_or_fetch(
+                "eth_getTransactionReceipt",
+                json!([hash]),
+                CachePolicy::UntilSealed,
+                || async move {
+                    let client = self.create_client();
+                    observe_upstream(
+                        "eth_getTransactionReceipt",
+                        client.get_transaction_receipt(hash),
+                    )
+                    .await
+                    .map_err(upstream_error)
+                },
+            )
             .await
-            .map_err(|_| ErrorObject::from(ErrorCode::InternalError).into_owned())
     }
 
     async fn protocol_version(&self) -> RpcResult<String> {
+        self.allow_method("eth_protocolVersion")?;
         let client = self.create_client();
-        client
-            .protocol_version()
+        observe_upstream("eth_protocolVersion", client.protocol_version())
             .await
-            .map_err(|_| ErrorObject::from(ErrorCode::InternalError).into_owned())
+            .map_err(upstream_error)
     }
 
-    // Sending raw transactions is allowed.
     async fn send_raw_transaction(&self, tx_bytes: Bytes) -> RpcResult<H256> {
+        self.allow_method("eth_sendRawTransaction")?;
+        self.allow_tx(&tx_bytes)?;
         let client = self.create_client();
-        client
-            .send_raw_transaction(tx_bytes)
-            .await
-            .map_err(|_| ErrorObject::from(ErrorCode::InternalError).into_owned())
+        observe_upstream(
+            "eth_sendRawTransaction",
+            client.send_raw_transaction(tx_bytes),
+        )
+        .await
+        .map_err(upstream_error)
     }
 
     async fn syncing(&self) -> RpcResult<SyncState> {
+        self.allow_method("eth_syncing")?;
         let client = self.create_client();
-        client
-            .syncing()
+        observe_upstream("eth_syncing", client.syncing())
             .await
-            .map_err(|_| ErrorObject::from(ErrorCode::InternalError).into_owned())
+            .map_err(upstream_error)
     }
 
     async fn accounts(&self) -> RpcResult<Vec<Address>> {
-        Err(ErrorObject::from(ErrorCode::ServerError(403)))
+        Err(self.method_not_allowed("eth_accounts"))
     }
 
     async fn coinbase(&self) -> RpcResult<Address> {
+        self.allow_method("eth_coinbase")?;
         let client = self.create_client();
-        client
-            .coinbase()
+        observe_upstream("eth_coinbase", client.coinbase())
             .await
-            .map_err(|_| ErrorObject::from(ErrorCode::InternalError).into_owned())
+            .map_err(upstream_error)
     }
 
     async fn compilers(&self) -> RpcResult<Vec<String>> {
+        self.allow_method("eth_getCompilers")?;
         let client = self.create_client();
-        client
-            .compilers()
+        observe_upstream("eth_getCompilers", client.compilers())
             .await
-            .map_err(|_| ErrorObject::from(ErrorCode::InternalError).into_owned())
+            .map_err(upstream_error)
     }
 
     async fn hashrate(&self) -> RpcResult<U256> {
+        self.allow_method("eth_hashrate")?;
         let client = self.create_client();
-        client
-            .hashrate()
+        observe_upstream("eth_hashrate", client.hashrate())
             .await
-            .map_err(|_| ErrorObject::from(ErrorCode::InternalError).into_owned())
+            .map_err(upstream_error)
     }
 
     async fn get_uncle_count_by_block_hash(&self, hash: H256) -> RpcResult<Option<U256>> {
+        self.allow_method("eth_getUncleCountByBlockHash")?;
         let client = self.create_client();
-        client
-            .get_uncle_count_by_block_hash(hash)
-            .await
-            .map_err(|_| ErrorObject::from(ErrorCode::InternalError).into_owned())
+        observe_upstream(
+            "eth_getUncleCountByBlockHash",
+            client.get_uncle_count_by_block_hash(hash),
+        )
+        .await
+        .map_err(upstream_error)
     }
 
     async fn get_uncle_count_by_block_number(
         &self,
         number: BlockNumber,
     ) -> RpcResult<Option<U256>> {
+        self.allow_method("eth_getUncleCountByBlockNumber")?;
         let client = self.create_client();
-        client
-            .get_uncle_count_by_block_number(number)
-            .await
-            .map_err(|_| ErrorObject::from(ErrorCode::InternalError).into_owned())
+        observe_upstream(
+            "eth_getUncleCountByBlockNumber",
+            client.get_uncle_count_by_block_number(number),
+        )
+        .await
+        .map_err(upstream_error)
     }
 
     async fn mining(&self) -> RpcResult<bool> {
-        Err(ErrorObject::from(ErrorCode::ServerError(403)))
+        Err(self.method_not_allowed("eth_mining"))
     }
 
     async fn fee_history(
@@ -501,10 +1090,331 @@ impl EthNamespaceServer for Proxy {
         newest_block: BlockNumber,
         reward_percentiles: Vec<f32>,
     ) -> RpcResult<FeeHistory> {
+        self.allow_method("eth_feeHistory")?;
         let client = self.create_client();
-        client
-            .fee_history(block_count, newest_block, reward_percentiles)
+        let mut history = observe_upstream(
+            "eth_feeHistory",
+            client.fee_history(block_count, newest_block, reward_percentiles),
+        )
+        .await
+        .map_err(upstream_error)?;
+        // Gas used ratios tell as much as the gas used of the blocks.
+        self.redaction.apply_fee_history(&mut history);
+        Ok(history)
+    }
+}
+
+#[async_trait]
+impl ZksNamespaceServer for Proxy {
+    async fn estimate_fee(&self, req: CallRequest) -> RpcResult<Fee> {
+        self.allow_method("zks_estimateFee")?;
+        self.estimation
+            .allow_public("zks_estimateFee", &req)
+            .map_err(|denial| self.deny(denial))?;
+        self.allow_unauthorized_call(&req)
             .await
-            .map_err(|_| ErrorObject::from(ErrorCode::InternalError).into_owned())
+            .map_err(|denial| self.deny(denial))?;
+        let client = self.create_client();
+        observe_upstream("zks_estimateFee", client.estimate_fee(req))
+            .await
+            .map_err(|err| self.estimation.public_error(err))
+    }
+
+    async fn estimate_gas_l1_to_l2(&self, req: CallRequest) -> RpcResult<U256> {
+        self.allow_method("zks_estimateGasL1ToL2")?;
+        self.estimation
+            .allow_public("zks_estimateGasL1ToL2", &req)
+            .map_err(|denial| self.deny(denial))?;
+        self.allow_unauthorized_call(&req)
+            .await
+            .map_err(|denial| self.deny(denial))?;
+        let client = self.create_client();
+        observe_upstream("zks_estimateGasL1ToL2", client.estimate_gas_l1_to_l2(req))
+            .await
+            .map_err(|err| self.estimation.public_error(err))
+    }
+
+    async fn get_bridgehub_contract(&self) -> RpcResult<Option<Address>> {
+        self.allow_method("zks_getBridgehubContract")?;
+        let client = self.create_client();
+        observe_upstream("zks_getBridgehubContract", client.get_bridgehub_contract())
+            .await
+            .map_err(upstream_error)
+    }
+
+    async fn get_main_contract(&self) -> RpcResult<Address> {
+        self.allow_method("zks_getMainContract")?;
+        let client = self.create_client();
+        observe_upstream("zks_getMainContract", client.get_main_contract())
+            .await
+            .map_err(upstream_error)
+    }
+
+    async fn get_testnet_paymaster(&self) -> RpcResult<Option<Address>> {
+        self.allow_method("zks_getTestnetPaymaster")?;
+        let client = self.create_client();
+        observe_upstream("zks_getTestnetPaymaster", client.get_testnet_paymaster())
+            .await
+            .map_err(upstream_error)
+    }
+
+    async fn get_bridge_contracts(&self) -> RpcResult<BridgeAddresses> {
+        self.allow_method("zks_getBridgeContracts")?;
+        let client = self.create_client();
+        observe_upstream("zks_getBridgeContracts", client.get_bridge_contracts())
+            .await
+            .map_err(upstream_error)
+    }
+
+    async fn l1_chain_id(&self) -> RpcResult<U64> {
+        self.allow_method("zks_L1ChainId")?;
+        let client = self.create_client();
+        observe_upstream("zks_L1ChainId", client.l1_chain_id())
+            .await
+            .map_err(upstream_error)
+    }
+
+    async fn get_confirmed_tokens(&self, from: u32, limit: u8) -> RpcResult<Vec<Token>> {
+        self.allow_method("zks_getConfirmedTokens")?;
+        let client = self.create_client();
+        observe_upstream(
+            "zks_getConfirmedTokens",
+            client.get_confirmed_tokens(from, limit),
+        )
+        .await
+        .map_err(upstream_error)
+    }
+
+    // Balances are private - available only through privateeth_getAllAccountBalances.
+    async fn get_all_account_balances(
+        &self,
+        _address: Address,
+    ) -> RpcResult<HashMap<Address, U256>> {
+        Err(self.method_not_allowed("zks_getAllAccountBalances"))
+    }
+
+    async fn get_l2_to_l1_msg_proof(
+        &self,
+        block: L2BlockNumber,
+        sender: Address,
+        msg: H256,
+        l2_log_position: Option<usize>,
+    ) -> RpcResult<Option<L2ToL1LogProof>> {
+        self.allow_method("zks_getL2ToL1MsgProof")?;
+        let client = self.create_client();
+        observe_upstream(
+            "zks_getL2ToL1MsgProof",
+            client.get_l2_to_l1_msg_proof(block, sender, msg, l2_log_position),
+        )
+        .await
+        .map_err(upstream_error)
+    }
+
+    // Proof is allowed - if you know the tx hash.
+    async fn get_l2_to_l1_log_proof(
+        &self,
+        tx_hash: H256,
+        index: Option<usize>,
+    ) -> RpcResult<Option<L2ToL1LogProof>> {
+        self.allow_method("zks_getL2ToL1LogProof")?;
+        let client = self.create_client();
+        observe_upstream(
+            "zks_getL2ToL1LogProof",
+            client.get_l2_to_l1_log_proof(tx_hash, index),
+        )
+        .await
+        .map_err(upstream_error)
+    }
+
+    async fn get_l1_batch_number(&self) -> RpcResult<U64> {
+        self.allow_method("zks_L1BatchNumber")?;
+        let client = self.create_client();
+        observe_upstream("zks_L1BatchNumber", client.get_l1_batch_number())
+            .await
+            .map_err(upstream_error)
+    }
+
+    async fn get_l2_block_range(&self, batch: L1BatchNumber) -> RpcResult<Option<(U64, U64)>> {
+        self.allow_method("zks_getL1BatchBlockRange")?;
+        let client = self.create_client();
+        observe_upstream("zks_getL1BatchBlockRange", client.get_l2_block_range(batch))
+            .await
+            .map_err(upstream_error)
+    }
+
+    // Block details only contain the counts - the transactions themselves are not listed.
+    async fn get_block_details(
+        &self,
+        block_number: L2BlockNumber,
+    ) -> RpcResult<Option<BlockDetails>> {
+        self.allow_method("zks_getBlockDetails")?;
+        let client = self.create_client();
+        let mut details = observe_upstream(
+            "zks_getBlockDetails",
+            client.get_block_details(block_number),
+        )
+        .await
+        .map_err(upstream_error)?;
+        if let Some(details) = &mut details {
+            self.redaction.apply_details(&mut details.base);
+        }
+        Ok(details)
+    }
+
+    // Details are allowed - if you know the hash.
+    async fn get_transaction_details(&self, hash: H256) -> RpcResult<Option<TransactionDetails>> {
+        self.allow_method("zks_getTransactionDetails")?;
+        let client = self.create_client();
+        observe_upstream(
+            "zks_getTransactionDetails",
+            client.get_transaction_details(hash),
+        )
+        .await
+        .map_err(upstream_error)
+    }
+
+    // Listing block transactions is not allowed.
+    async fn get_raw_block_transactions(
+        &self,
+        _block_number: L2BlockNumber,
+    ) -> RpcResult<Vec<zksync_types::Transaction>> {
+        Err(self.method_not_allowed("zks_getRawBlockTransactions"))
+    }
+
+    async fn get_l1_batch_details(
+        &self,
+        batch: L1BatchNumber,
+    ) -> RpcResult<Option<L1BatchDetails>> {
+        self.allow_method("zks_getL1BatchDetails")?;
+        let client = self.create_client();
+        let mut details =
+            observe_upstream("zks_getL1BatchDetails", client.get_l1_batch_details(batch))
+                .await
+                .map_err(upstream_error)?;
+        if let Some(details) = &mut details {
+            self.redaction.apply_details(&mut details.base);
+        }
+        Ok(details)
+    }
+
+    async fn get_bytecode_by_hash(&self, hash: H256) -> RpcResult<Option<Vec<u8>>> {
+        self.allow_method("zks_getBytecodeByHash")?;
+        let client = self.create_client();
+        observe_upstream("zks_getBytecodeByHash", client.get_bytecode_by_hash(hash))
+            .await
+            .map_err(upstream_error)
+    }
+
+    async fn get_l1_gas_price(&self) -> RpcResult<U64> {
+        self.allow_method("zks_getL1GasPrice")?;
+        let client = self.create_client();
+        observe_upstream("zks_getL1GasPrice", client.get_l1_gas_price())
+            .await
+            .map_err(upstream_error)
+    }
+
+    async fn get_fee_params(&self) -> RpcResult<FeeParams> {
+        self.allow_method("zks_getFeeParams")?;
+        let client = self.create_client();
+        observe_upstream("zks_getFeeParams", client.get_fee_params())
+            .await
+            .map_err(upstream_error)
+    }
+
+    async fn get_protocol_version(
+        &self,
+        version_id: Option<u16>,
+    ) -> RpcResult<Option<ProtocolVersion>> {
+        self.allow_method("zks_getProtocolVersion")?;
+        let client = self.create_client();
+        observe_upstream(
+            "zks_getProtocolVersion",
+            client.get_protocol_version(version_id),
+        )
+        .await
+        .map_err(upstream_error)
+    }
+
+    // Storage proofs would reveal storage - not allowed (same as eth_getStorageAt).
+    async fn get_proof(
+        &self,
+        _address: Address,
+        _keys: Vec<H256>,
+        _l1_batch_number: L1BatchNumber,
+    ) -> RpcResult<Option<Proof>> {
+        Err(self.method_not_allowed("zks_getProof"))
+    }
+
+    async fn get_batch_fee_input(&self) -> RpcResult<PubdataIndependentBatchFeeModelInput> {
+        self.allow_method("zks_getBatchFeeInput")?;
+        let client = self.create_client();
+        observe_upstream("zks_getBatchFeeInput", client.get_batch_fee_input())
+            .await
+            .map_err(upstream_error)
+    }
+
+    // Sending raw transactions is allowed, but the output would show every storage slot and
+    // event the transaction touched. Storage logs are dropped, and as the caller isn't known
+    // to be the sender, events go through the same rules as logs without credentials.
+    async fn send_raw_transaction_with_detailed_output(
+        &self,
+        tx_bytes: Bytes,
+    ) -> RpcResult<TransactionDetailedResult> {
+        self.allow_method("zks_sendRawTransactionWithDetailedOutput")?;
+        self.allow_tx(&tx_bytes)?;
+        let client = self.create_client();
+        let mut result = observe_upstream(
+            "zks_sendRawTransactionWithDetailedOutput",
+            client.send_raw_transaction_with_detailed_output(tx_bytes),
+        )
+        .await
+        .map_err(upstream_error)?;
+        result.storage_logs.clear();
+        result
+            .events
+            .retain(|event| self.policy.evaluate_log(event, None).is_allowed());
+        Ok(result)
+    }
+}
+
+/// Serves net_ and web3_ methods, which don't need to go to the sequencer (except for the
+/// chain id, which is cached).
+#[derive(Clone)]
+pub struct NodeInfo {
+    pub proxy: Proxy,
+}
+
+impl NodeInfo {
+    /// net_ methods - registered here rather than through NetNamespaceServer, whose
+    /// net_version can't wait for the chain id.
+    pub fn net_rpc(self) -> RpcModule<NodeInfo> {
+        let mut rpc = RpcModule::new(self);
+        rpc.register_async_method("net_version", |_, node| async move {
+            EthNamespaceServer::chain_id(&node.proxy)
+                .await
+                .map(|chain_id| chain_id.as_u64().to_string())
+        })
+        .unwrap();
+        // Peers of the sequencer are not exposed.
+        rpc.register_method("net_peerCount", |_, node| -> RpcResult<U256> {
+            let denial = Denial::MethodNotAllowed {
+                method: "net_peerCount",
+            };
+            metrics::record_denial(&denial);
+            Err(denial.into_error(node.proxy.verbose_errors))
+        })
+        .unwrap();
+        rpc.register_method("net_listening", |_, _| -> RpcResult<bool> { Ok(true) })
+            .unwrap();
+        rpc
+    }
+}
+
+impl Web3NamespaceServer for NodeInfo {
+    fn client_version(&self) -> RpcResult<String> {
+        Ok(format!(
+            "doubleo-privacy-proxy/v{}",
+            env!("CARGO_PKG_VERSION")
+        ))
     }
 }
@@ -0,0 +1,538 @@
+use std::{
+    borrow::Cow,
+    collections::HashSet,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use futures::future::BoxFuture;
+use hyper::{header, Body, Request, Response, StatusCode};
+use serde::Deserialize;
+use serde_json::Value;
+use tower::{Layer, Service};
+use zksync_types::{
+    api::{BlockNumber, TransactionVariant},
+    url::SensitiveUrl,
+    U64,
+};
+use zksync_web3_decl::{
+    client::{Client, L2},
+    jsonrpsee::{
+        core::{async_trait, SubscriptionResult},
+        proc_macros::rpc,
+        server::{middleware::rpc::RpcServiceT, MethodResponse},
+        types::{error::ErrorCode, ErrorObject, ErrorObjectOwned, Request as RpcRequest},
+        PendingSubscriptionSink, SubscriptionMessage, SubscriptionSink,
+    },
+    namespaces::EthNamespaceClient,
+    types::{Block, Filter, Log},
+};
+
+use zksync_web3_decl::*;
+
+use crate::admin::{MAINTENANCE_CODE, MAINTENANCE_MSG};
+use crate::error::Denial;
+use crate::metrics;
+use crate::middleware::get_credentials_from_request;
+use crate::policy::Policy;
+use crate::proxy::{authorized_addresses, normalize_credentials, CredentialStore};
+use crate::rate_limit::{MethodClass, RateLimiter, RATE_LIMITED_CODE, RATE_LIMITED_MSG};
+use crate::redaction::BlockRedaction;
+use crate::rewrite::PRIVATE_METHODS;
+
+// If the subscription falls behind by more than this, older blocks are skipped.
+const MAX_BLOCKS_PER_POLL: u64 = 100;
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct SubscriptionConfig {
+    // How often subscriptions check the sequencer for new blocks and logs.
+    #[serde(default = "SubscriptionConfig::default_poll_interval_ms")]
+    pub poll_interval_ms: u64,
+}
+
+impl SubscriptionConfig {
+    fn default_poll_interval_ms() -> u64 {
+        1000
+    }
+}
+
+impl Default for SubscriptionConfig {
+    fn default() -> Self {
+        SubscriptionConfig {
+            poll_interval_ms: SubscriptionConfig::default_poll_interval_ms(),
+        }
+    }
+}
+
+#[rpc(server, namespace = "eth")]
+pub trait EthSubscribe {
+    /// 'newHeads' (headers without transactions) or 'logs' (only from fully whitelisted contracts).
+    #[subscription(name = "subscribe" => "subscription", unsubscribe = "unsubscribe", item = Value)]
+    async fn subscribe(&self, kind: String, filter: Option<Filter>) -> SubscriptionResult;
+}
+
+// jsonrpsee doesn't pass the websocket handshake to the handlers, so the credential
+// has to be repeated here. It must be the one from the handshake (see WsCallLayer).
+#[rpc(server, namespace = "privateeth")]
+pub trait PrivateEthSubscribe {
+    /// Like eth_subscribe, but 'logs' also include events that mention the credential's addresses.
+    #[subscription(name = "subscribe" => "subscription", unsubscribe = "unsubscribe", item = Value)]
+    async fn private_subscribe(
+        &self,
+        credentials: String,
+        kind: String,
+        filter: Option<Filter>,
+    ) -> SubscriptionResult;
+}
+
+#[derive(Clone)]
+pub struct Subscriptions {
+    pub sequencer_url: String,
+    pub policy: Arc<dyn Policy>,
+    pub credentials: CredentialStore,
+    pub poll_interval: Duration,
+    pub redaction: BlockRedaction,
+    // Whether denials should explain which rule rejected the request.
+    pub verbose_errors: bool,
+}
+
+enum SubscriptionKind {
+    NewHeads,
+    Logs(Filter),
+}
+
+impl Subscriptions {
+    pub fn create_client(&self) -> Client<L2> {
+        let url = SensitiveUrl::from_str(&self.sequencer_url)
+            .unwrap_or_else(|_| panic!("Unable to parse client URL: {}", &self.sequencer_url));
+        Client::http(url)
+            .unwrap_or_else(|_| {
+                panic!("Unable to create a client for fork: {}", self.sequencer_url)
+            })
+            .build()
+    }
+
+    fn deny(&self, denial: Denial) -> ErrorObjectOwned {
+        metrics::record_denial(&denial);
+        denial.into_error(self.verbose_errors)
+    }
+
+    fn parse_kind(
+        &self,
+        kind: &str,
+        filter: Option<Filter>,
+    ) -> Result<SubscriptionKind, ErrorObjectOwned> {
+        match kind {
+            "newHeads" => Ok(SubscriptionKind::NewHeads),
+            "logs" => Ok(SubscriptionKind::Logs(filter.unwrap_or_default())),
+            // Pending transactions would leak who is sending what.
+            "newPendingTransactions" => Err(self.deny(Denial::MethodNotAllowed {
+                method: "eth_subscribe(newPendingTransactions)",
+            })),
+            _ => Err(ErrorObject::owned(
+                ErrorCode::InvalidParams.code(),
+                format!("unsupported subscription: {}", kind),
+                None::<()>,
+            )),
+        }
+    }
+
+    // Waits for the next poll. Returns false once the subscriber is gone.
+    async fn wait(&self, sink: &SubscriptionSink) -> bool {
+        tokio::select! {
+            _ = sink.closed() => false,
+            _ = tokio::time::sleep(self.poll_interval) => true,
+        }
+    }
+
+    // Returns the range of blocks that appeared since `last_sent`.
+    async fn new_blocks(
+        &self,
+        client: &Client<L2>,
+        last_sent: &mut Option<u64>,
+    ) -> Option<(u64, u64)> {
+        let latest = match client.get_block_number().await {
+            Ok(latest) => latest.as_u64(),
+            Err(err) => {
+                tracing::warn!("Subscription failed to fetch block number: {}", err);
+                return None;
+            }
+        };
+        let from = match last_sent {
+            Some(last_sent) => *last_sent + 1,
+            // Start with the current block.
+            None => latest,
+        };
+        if from > latest {
+            return None;
+        }
+        *last_sent = Some(latest);
+        Some((
+            from.max(latest.saturating_sub(MAX_BLOCKS_PER_POLL - 1)),
+            latest,
+        ))
+    }
+
+    async fn send(sink: &SubscriptionSink, item: &impl serde::Serialize) -> bool {
+        match SubscriptionMessage::from_json(item) {
+            Ok(message) => sink.send(message).await.is_ok(),
+            Err(err) => {
+                tracing::warn!("Failed to serialize subscription item: {}", err);
+                true
+            }
+        }
+    }
+
+    async fn new_heads(&self, sink: SubscriptionSink) {
+        let client = self.create_client();
+        let mut last_sent = None;
+        while self.wait(&sink).await {
+            let Some((from, to)) = self.new_blocks(&client, &mut last_sent).await else {
+                continue;
+            };
+            for number in from..=to {
+                let block = client
+                    .get_block_by_number(BlockNumber::Number(U64::from(number)), false)
+                    .await;
+                match block {
+                    Ok(Some(block)) => {
+                        if !Subscriptions::send(&sink, &self.header(block)).await {
+                            return;
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(err) => tracing::warn!("Subscription failed to fetch block: {}", err),
+                }
+            }
+        }
+    }
+
+    // Sends logs matching the filter. Logs are checked against the policy and - if the
+    // subscription was made with credentials - the addresses currently bound to them.
+    async fn logs(&self, sink: SubscriptionSink, filter: Filter, credentials: Option<String>) {
+        let client = self.create_client();
+        let mut last_sent = None;
+        while self.wait(&sink).await {
+            let users = match &credentials {
+                Some(credentials) => match authorized_addresses(&self.credentials, credentials) {
+                    Ok(users) => Some(users),
+                    // Credential was revoked - end the subscription.
+                    Err(_) => return,
+                },
+                None => None,
+            };
+            let Some((from, to)) = self.new_blocks(&client, &mut last_sent).await else {
+                continue;
+            };
+            let filter = Filter {
+                from_block: Some(BlockNumber::Number(U64::from(from))),
+                to_block: Some(BlockNumber::Number(U64::from(to))),
+                block_hash: None,
+                ..filter.clone()
+            };
+            let logs: Vec<Log> = match client.get_logs(filter).await {
+                Ok(logs) => logs,
+                Err(err) => {
+                    tracing::warn!("Subscription failed to fetch logs: {}", err);
+                    continue;
+                }
+            };
+            for log in logs {
+                let allowed = self.policy.evaluate_log(&log, users.as_ref()).is_allowed();
+                if allowed && !Subscriptions::send(&sink, &log).await {
+                    return;
+                }
+            }
+        }
+    }
+
+    // Block header as sent by 'newHeads' - without the transactions field.
+    fn header(&self, mut block: Block<TransactionVariant>) -> Value {
+        self.redaction.apply(&mut block);
+        let mut header = serde_json::to_value(block).unwrap_or_default();
+        if let Some(header) = header.as_object_mut() {
+            header.remove("transactions");
+        }
+        header
+    }
+
+    async fn run(
+        &self,
+        sink: SubscriptionSink,
+        kind: SubscriptionKind,
+        credentials: Option<String>,
+    ) {
+        match kind {
+            SubscriptionKind::NewHeads => self.new_heads(sink).await,
+            SubscriptionKind::Logs(filter) => self.logs(sink, filter, credentials).await,
+        }
+    }
+}
+
+#[async_trait]
+impl EthSubscribeServer for Subscriptions {
+    async fn subscribe(
+        &self,
+        pending: PendingSubscriptionSink,
+        kind: String,
+        filter: Option<Filter>,
+    ) -> SubscriptionResult {
+        if let Err(denial) = self
+            .policy
+            .evaluate_method("eth_subscribe", None)
+            .into_result()
+        {
+            pending.reject(self.deny(denial)).await;
+            return Ok(());
+        }
+        let kind = match self.parse_kind(&kind, filter) {
+            Ok(kind) => kind,
+            Err(err) => {
+                pending.reject(err).await;
+                return Ok(());
+            }
+        };
+        let sink = pending.accept().await?;
+        self.run(sink, kind, None).await;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl PrivateEthSubscribeServer for Subscriptions {
+    async fn private_subscribe(
+        &self,
+        pending: PendingSubscriptionSink,
+        credentials: String,
+        kind: String,
+        filter: Option<Filter>,
+    ) -> SubscriptionResult {
+        let allowed = authorized_addresses(&self.credentials, &credentials).and_then(|users| {
+            self.policy
+                .evaluate_method("eth_subscribe", Some(&users))
+                .into_result()
+        });
+        if let Err(denial) = allowed {
+            pending.reject(self.deny(denial)).await;
+            return Ok(());
+        }
+        let kind = match self.parse_kind(&kind, filter) {
+            Ok(kind) => kind,
+            Err(err) => {
+                pending.reject(err).await;
+                return Ok(());
+            }
+        };
+        let sink = pending.accept().await?;
+        self.run(sink, kind, Some(credentials)).await;
+        Ok(())
+    }
+}
+
+pub(crate) fn is_websocket_upgrade(req: &Request<Body>) -> bool {
+    req.headers()
+        .get(header::UPGRADE)
+        .and_then(|upgrade| upgrade.to_str().ok())
+        .map(|upgrade| upgrade.eq_ignore_ascii_case("websocket"))
+        .unwrap_or(false)
+}
+
+// Middleware that rejects websocket connections made with unknown credentials,
+// so that clients find out at connection time (and not with the first subscription).
+#[derive(Clone)]
+pub struct WsAuthMiddleware<S> {
+    inner: S,
+    credentials: CredentialStore,
+}
+
+impl<S> Service<Request<Body>> for WsAuthMiddleware<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>> + Send + Clone + 'static,
+    S::Future: Send,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let mut inner = self.inner.clone();
+        let credentials = self.credentials.clone();
+        Box::pin(async move {
+            if is_websocket_upgrade(&req) {
+                if let Some(request_credentials) = get_credentials_from_request(&req) {
+                    if let Err(denial) = authorized_addresses(&credentials, &request_credentials) {
+                        metrics::record_denial(&denial);
+                        return Ok(Response::builder()
+                            .status(StatusCode::UNAUTHORIZED)
+                            .body(Body::from(denial.reason()))
+                            .unwrap());
+                    }
+                }
+            }
+            inner.call(req).await
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct WsAuthLayer {
+    pub credentials: CredentialStore,
+}
+
+impl<S> Layer<S> for WsAuthLayer {
+    type Service = WsAuthMiddleware<S>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        WsAuthMiddleware {
+            inner: service,
+            credentials: self.credentials.clone(),
+        }
+    }
+}
+
+// Checks for the calls made over a websocket connection - the http middleware only sees
+// the handshake. Calls are rejected during maintenance, count against the rate limits of
+// the connection's client and are recorded in the metrics.
+//
+// Calls are bound to the credential of the handshake, like HTTP requests are to theirs: on
+// connections made with one, methods with a private version (PRIVATE_METHODS, and
+// eth_subscribe) are served as that version with the credential, and private versions are
+// only accepted with it.
+#[derive(Clone)]
+pub struct WsCallMiddleware<S> {
+    inner: S,
+    layer: WsCallLayer,
+}
+
+// Private version of the method (that takes the credential as the first param).
+fn private_method(method: &str) -> Option<&'static str> {
+    match method {
+        "eth_subscribe" => Some("privateeth_subscribe"),
+        method => PRIVATE_METHODS
+            .iter()
+            .find(|(public, _)| *public == method)
+            .map(|(_, private)| *private),
+    }
+}
+
+fn takes_credential(method: &str) -> bool {
+    method == "privateeth_subscribe"
+        || PRIVATE_METHODS
+            .iter()
+            .any(|(_, private)| *private == method)
+}
+
+impl<S> WsCallMiddleware<S> {
+    fn bind_credentials(&self, req: &mut RpcRequest) -> Result<(), Denial> {
+        let mut params: Vec<Value> = req
+            .params
+            .as_ref()
+            .and_then(|params| serde_json::from_str(params.get()).ok())
+            .unwrap_or_default();
+        let method = req.method.to_string();
+        match (private_method(&method), &self.layer.credentials) {
+            (Some(private), Some(credentials)) => {
+                metrics::record_rewrite(&method, private);
+                params.insert(0, Value::String(credentials.clone()));
+                req.method = private.into();
+                req.params = serde_json::value::to_raw_value(&params)
+                    .ok()
+                    .map(Cow::Owned);
+                return Ok(());
+            }
+            (Some(_), None) => return Ok(()),
+            (None, _) => {}
+        }
+        match (method.as_str(), &self.layer.credentials) {
+            ("eth_unsubscribe", Some(_)) => req.method = "privateeth_unsubscribe".into(),
+            (method, None) if takes_credential(method) => return Err(Denial::MissingCredential),
+            (method, Some(credentials)) if takes_credential(method) => {
+                let requested = params
+                    .first()
+                    .and_then(Value::as_str)
+                    .map(normalize_credentials);
+                if requested != Some(normalize_credentials(credentials)) {
+                    return Err(Denial::UnknownCredential);
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+fn reject<'a>(req: RpcRequest<'a>, error: ErrorObjectOwned) -> BoxFuture<'a, MethodResponse> {
+    let response = MethodResponse::error(req.id, error);
+    Box::pin(async move { response })
+}
+
+impl<'a, S> RpcServiceT<'a> for WsCallMiddleware<S>
+where
+    S: RpcServiceT<'a>,
+    S::Future: 'a,
+{
+    type Future = BoxFuture<'a, MethodResponse>;
+
+    fn call(&self, mut req: RpcRequest<'a>) -> Self::Future {
+        let layer = &self.layer;
+        if layer.maintenance.load(Ordering::Relaxed) {
+            let error = ErrorObject::owned(MAINTENANCE_CODE, MAINTENANCE_MSG, None::<()>);
+            return reject(req, error);
+        }
+        let method = req.method.to_string();
+        let allowed = layer.limiter.try_acquire(
+            layer.client_ip.as_deref(),
+            layer.credentials.as_deref(),
+            &[MethodClass::of(&method)],
+        );
+        if !allowed {
+            let error = ErrorObject::owned(RATE_LIMITED_CODE, RATE_LIMITED_MSG, None::<()>);
+            return reject(req, error);
+        }
+        if let Err(denial) = self.bind_credentials(&mut req) {
+            metrics::record_denial(&denial);
+            return reject(req, denial.into_error(layer.verbose_errors));
+        }
+
+        let known_methods = layer.known_methods.clone();
+        let start = Instant::now();
+        let response = self.inner.call(req);
+        Box::pin(async move {
+            let response = response.await;
+            metrics::record_request(&method, &known_methods, start.elapsed().as_secs_f64());
+            response
+        })
+    }
+}
+
+/// RPC middleware for one websocket connection.
+#[derive(Clone)]
+pub struct WsCallLayer {
+    pub limiter: Arc<RateLimiter>,
+    pub maintenance: Arc<AtomicBool>,
+    pub known_methods: Arc<HashSet<String>>,
+    pub verbose_errors: bool,
+    // Client and credential of the handshake.
+    pub client_ip: Option<String>,
+    pub credentials: Option<String>,
+}
+
+impl<S> Layer<S> for WsCallLayer {
+    type Service = WsCallMiddleware<S>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        WsCallMiddleware {
+            inner: service,
+            layer: self.clone(),
+        }
+    }
+}
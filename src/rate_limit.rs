@@ -0,0 +1,360 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use futures::future::BoxFuture;
+use hyper::{header, Body, Request, Response, StatusCode};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tower::{Layer, Service};
+
+use crate::middleware::get_credentials_from_request;
+use crate::pubsub::is_websocket_upgrade;
+
+/// Error code for requests rejected by the rate limiter (as used by EIP-1474 'Limit exceeded').
+pub const RATE_LIMITED_CODE: i32 = -32005;
+pub const RATE_LIMITED_MSG: &str = "rate limit exceeded";
+
+// When the number of buckets grows above this, fully refilled (idle) buckets are dropped.
+const MAX_BUCKETS_BEFORE_PRUNE: usize = 100_000;
+
+#[derive(Debug, Deserialize, Clone, Copy)]
+pub struct BucketConfig {
+    // Maximum number of requests that can be done in a burst.
+    pub burst: u32,
+    // How many requests are added back to the bucket every second.
+    pub per_second: f64,
+}
+
+/// Budgets for each method class - missing entries are not limited.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct ClassBudgets {
+    pub reads: Option<BucketConfig>,
+    pub calls: Option<BucketConfig>,
+    pub send_raw_transaction: Option<BucketConfig>,
+    pub auth: Option<BucketConfig>,
+}
+
+impl ClassBudgets {
+    fn get(&self, class: MethodClass) -> Option<BucketConfig> {
+        match class {
+            MethodClass::Read => self.reads,
+            MethodClass::Call => self.calls,
+            MethodClass::SendRawTransaction => self.send_raw_transaction,
+            MethodClass::Auth => self.auth,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct RateLimitConfig {
+    // Limits per client IP - the address of the connection, see `trusted_proxies`.
+    #[serde(default)]
+    pub per_ip: ClassBudgets,
+    // Limits per credential (from the authorization header or url path).
+    #[serde(default)]
+    pub per_credential: ClassBudgets,
+    // Reverse proxies in front of the proxy. For their connections, the client is the
+    // rightmost address in X-Forwarded-For that is not one of them (or X-Real-IP).
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+/// Address of the connection that the request came on (set by `BuiltProxy::start`).
+#[derive(Debug, Clone, Copy)]
+pub struct PeerAddr(pub SocketAddr);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MethodClass {
+    Read,
+    Call,
+    SendRawTransaction,
+    Auth,
+}
+
+impl MethodClass {
+    pub fn of(method: &str) -> Self {
+        match method {
+            "eth_call"
+            | "eth_estimateGas"
+            | "zks_estimateFee"
+            | "zks_estimateGasL1ToL2"
+            | "privateeth_call"
+            | "privateeth_estimateGas"
+            | "privateeth_estimateFee" => MethodClass::Call,
+            "eth_sendRawTransaction" | "zks_sendRawTransactionWithDetailedOutput" => {
+                MethodClass::SendRawTransaction
+            }
+            "privateeth_addCredential" | "privateeth_checkCredential" => MethodClass::Auth,
+            _ => MethodClass::Read,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum BucketKey {
+    Ip(String, MethodClass),
+    Credential(String, MethodClass),
+}
+
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, config: &BucketConfig, now: Instant) {
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * config.per_second).min(config.burst as f64);
+        self.last_refill = now;
+    }
+}
+
+/// Token buckets shared by all connections.
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: Mutex<HashMap<BucketKey, (Bucket, BucketConfig)>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        RateLimiter {
+            config,
+            buckets: Default::default(),
+        }
+    }
+
+    /// Client of the request: the peer of the connection, unless that is a trusted proxy.
+    /// X-Forwarded-For is read from the right, as the client can put anything on the left.
+    pub(crate) fn client_ip(&self, req: &Request<Body>) -> Option<String> {
+        let peer = req.extensions().get::<PeerAddr>()?.0.ip();
+        let trusted = |ip: &IpAddr| self.config.trusted_proxies.contains(ip);
+        if !trusted(&peer) {
+            return Some(peer.to_string());
+        }
+        let forwarded: Vec<&str> = req
+            .headers()
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .collect();
+        if forwarded.is_empty() {
+            if let Some(ip) = req
+                .headers()
+                .get("x-real-ip")
+                .and_then(|ip| ip.to_str().ok())
+                .and_then(|ip| ip.trim().parse::<IpAddr>().ok())
+            {
+                return Some(ip.to_string());
+            }
+        }
+        let mut client = peer;
+        for ip in forwarded.into_iter().rev() {
+            match ip.trim().parse::<IpAddr>() {
+                Ok(ip) if trusted(&ip) => client = ip,
+                Ok(ip) => return Some(ip.to_string()),
+                // Garbage can only come from the client - the last valid hop is as far as we get.
+                Err(_) => break,
+            }
+        }
+        Some(client.to_string())
+    }
+
+    /// Takes one token for each of the calls. Returns false (and takes nothing) if any
+    /// of the buckets is empty.
+    pub(crate) fn try_acquire(
+        &self,
+        ip: Option<&str>,
+        credentials: Option<&str>,
+        calls: &[MethodClass],
+    ) -> bool {
+        let mut keys = vec![];
+        for class in calls {
+            if let Some(ip) = ip {
+                if let Some(config) = self.config.per_ip.get(*class) {
+                    keys.push((BucketKey::Ip(ip.to_string(), *class), config));
+                }
+            }
+            if let Some(credentials) = credentials {
+                if let Some(config) = self.config.per_credential.get(*class) {
+                    keys.push((
+                        BucketKey::Credential(credentials.to_string(), *class),
+                        config,
+                    ));
+                }
+            }
+        }
+        if keys.is_empty() {
+            return true;
+        }
+
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() > MAX_BUCKETS_BEFORE_PRUNE {
+            buckets.retain(|_, (bucket, config)| {
+                bucket.refill(config, now);
+                bucket.tokens < config.burst as f64
+            });
+        }
+
+        // Count how many tokens we need from each bucket (batches can hit the same one).
+        let mut needed: HashMap<BucketKey, (f64, BucketConfig)> = HashMap::new();
+        for (key, config) in keys {
+            needed.entry(key).or_insert((0.0, config)).0 += 1.0;
+        }
+
+        for (key, (count, config)) in &needed {
+            let (bucket, _) = buckets.entry(key.clone()).or_insert_with(|| {
+                (
+                    Bucket {
+                        tokens: config.burst as f64,
+                        last_refill: now,
+                    },
+                    *config,
+                )
+            });
+            bucket.refill(config, now);
+            if bucket.tokens < *count {
+                return false;
+            }
+        }
+        for (key, (count, _)) in &needed {
+            if let Some((bucket, _)) = buckets.get_mut(key) {
+                bucket.tokens -= count;
+            }
+        }
+        true
+    }
+}
+
+// Returns the class of each call in the request.
+fn classify_calls(body: &Value) -> Vec<MethodClass> {
+    let calls: Vec<&Value> = match body {
+        Value::Array(calls) => calls.iter().collect(),
+        call => vec![call],
+    };
+    calls
+        .into_iter()
+        .map(|call| MethodClass::of(call["method"].as_str().unwrap_or_default()))
+        .collect()
+}
+
+// Response with the same error for each call in the (single or batch) request.
+pub(crate) fn error_response(body: &Value, code: i32, message: &str) -> Response<Body> {
+    let error = |id: &Value| {
+        json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": code, "message": message },
+        })
+    };
+    let response = match body {
+        Value::Array(calls) => Value::Array(calls.iter().map(|call| error(&call["id"])).collect()),
+        call => error(&call["id"]),
+    };
+    Response::builder()
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(response.to_string()))
+        .unwrap()
+}
+
+// Middleware that enforces per-IP and per-credential request budgets.
+#[derive(Clone)]
+pub struct RateLimitMiddleware<S> {
+    inner: S,
+    limiter: Arc<RateLimiter>,
+}
+
+impl<S> Service<Request<Body>> for RateLimitMiddleware<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>> + Send + Clone + 'static,
+    S::Future: Send,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let mut inner = self.inner.clone();
+        let limiter = self.limiter.clone();
+        Box::pin(async move {
+            let ip = limiter.client_ip(&req);
+            let credentials = get_credentials_from_request(&req);
+
+            // The handshake checks the credential, so it counts as an auth call. Calls made
+            // over the connection are limited by the websocket RPC middleware.
+            if is_websocket_upgrade(&req) {
+                let class = match credentials {
+                    Some(_) => MethodClass::Auth,
+                    None => MethodClass::Read,
+                };
+                if !limiter.try_acquire(ip.as_deref(), credentials.as_deref(), &[class]) {
+                    return Ok(Response::builder()
+                        .status(StatusCode::TOO_MANY_REQUESTS)
+                        .body(Body::from(RATE_LIMITED_MSG))
+                        .unwrap());
+                }
+                return inner.call(req).await;
+            }
+
+            let (parts, body) = req.into_parts();
+            let body_bytes = hyper::body::to_bytes(body).await.unwrap_or_default();
+
+            // Requests that are not valid JSON are left for the server to reject.
+            if let Ok(body) = serde_json::from_slice::<Value>(&body_bytes) {
+                let calls = classify_calls(&body);
+                if !limiter.try_acquire(ip.as_deref(), credentials.as_deref(), &calls) {
+                    return Ok(error_response(&body, RATE_LIMITED_CODE, RATE_LIMITED_MSG));
+                }
+            }
+
+            inner
+                .call(Request::from_parts(parts, Body::from(body_bytes)))
+                .await
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct RateLimitLayer {
+    pub limiter: Arc<RateLimiter>,
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimitMiddleware<S>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        RateLimitMiddleware {
+            inner: service,
+            limiter: self.limiter.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detailed_send_counts_as_transaction() {
+        assert_eq!(
+            MethodClass::of("zks_sendRawTransactionWithDetailedOutput"),
+            MethodClass::SendRawTransaction
+        );
+    }
+
+    #[test]
+    fn l1_to_l2_estimation_counts_as_call() {
+        assert_eq!(MethodClass::of("zks_estimateGasL1ToL2"), MethodClass::Call);
+        assert_eq!(MethodClass::of("eth_estimateGas"), MethodClass::Call);
+    }
+}
@@ -0,0 +1,94 @@
+use serde::Deserialize;
+use zksync_types::{api::BlockDetailsBase, web3::FeeHistory, U256};
+use zksync_web3_decl::types::Block;
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum GasUsedRedaction {
+    #[default]
+    Keep,
+    // Rounded down to a multiple of 'gas_used_rounding'.
+    Round,
+    // Always zero.
+    Hide,
+}
+
+/// Which block fields are hidden - even without transactions, they reveal how busy
+/// the chain is (and can be correlated with the activity of a single user).
+#[derive(Debug, Deserialize, Clone)]
+pub struct BlockRedaction {
+    #[serde(default)]
+    pub zero_logs_bloom: bool,
+    #[serde(default)]
+    pub gas_used: GasUsedRedaction,
+    #[serde(default = "BlockRedaction::default_gas_used_rounding")]
+    pub gas_used_rounding: u64,
+    // Same for 'gasUsedRatio' in eth_feeHistory (a fraction of the block gas limit).
+    #[serde(default = "BlockRedaction::default_gas_used_ratio_rounding")]
+    pub gas_used_ratio_rounding: f64,
+    #[serde(default)]
+    pub hide_size: bool,
+    // Transaction counts in zks_ block / batch details, and eth_getBlockTransactionCountBy*.
+    #[serde(default)]
+    pub hide_transaction_counts: bool,
+}
+
+impl BlockRedaction {
+    fn default_gas_used_rounding() -> u64 {
+        1_000_000
+    }
+
+    fn default_gas_used_ratio_rounding() -> f64 {
+        0.01
+    }
+
+    pub fn apply<T>(&self, block: &mut Block<T>) {
+        if self.zero_logs_bloom {
+            block.logs_bloom = Default::default();
+        }
+        match self.gas_used {
+            GasUsedRedaction::Keep => {}
+            GasUsedRedaction::Round => {
+                let rounding = U256::from(self.gas_used_rounding.max(1));
+                block.gas_used = block.gas_used / rounding * rounding;
+            }
+            GasUsedRedaction::Hide => block.gas_used = U256::zero(),
+        }
+        if self.hide_size {
+            block.size = U256::zero();
+        }
+    }
+
+    pub fn apply_fee_history(&self, history: &mut FeeHistory) {
+        for ratio in &mut history.gas_used_ratio {
+            match self.gas_used {
+                GasUsedRedaction::Keep => {}
+                GasUsedRedaction::Round if self.gas_used_ratio_rounding > 0.0 => {
+                    let rounding = self.gas_used_ratio_rounding;
+                    *ratio = (*ratio / rounding).floor() * rounding;
+                }
+                GasUsedRedaction::Round | GasUsedRedaction::Hide => *ratio = 0.0,
+            }
+        }
+    }
+
+    pub fn apply_details(&self, details: &mut BlockDetailsBase) {
+        if self.hide_transaction_counts {
+            details.l1_tx_count = 0;
+            details.l2_tx_count = 0;
+        }
+    }
+}
+
+impl Default for BlockRedaction {
+    fn default() -> Self {
+        BlockRedaction {
+            zero_logs_bloom: false,
+            gas_used: GasUsedRedaction::Keep,
+            gas_used_rounding: BlockRedaction::default_gas_used_rounding(),
+            gas_used_ratio_rounding: BlockRedaction::default_gas_used_ratio_rounding(),
+            hide_size: false,
+            hide_transaction_counts: false,
+        }
+    }
+}
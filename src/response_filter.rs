@@ -0,0 +1,186 @@
+// Filtering of eth_call results that mix public and private data (e.g. `getAllOrders()`
+// returning everyone's orders). Entries declare the output ABI of the method, which output
+// is a list of records, and which field of a record holds its owner. Records that don't
+// belong to the caller are dropped (or zeroed, if the list has to keep its length).
+
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+    sync::RwLock,
+};
+
+use serde::{Deserialize, Serialize};
+use zksync_types::{
+    ethabi::{self, param_type::Reader, ParamType, Token},
+    transaction_request::CallRequest,
+    web3::Bytes,
+    Address,
+};
+
+use crate::config::WhitelistEntry;
+use crate::error::Denial;
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterAction {
+    #[default]
+    Drop,
+    // All fields of the record are set to zero (empty for dynamic types).
+    Zero,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ResponseFilter {
+    // Selector of the method (e.g. getAllOrders()).
+    pub selector: String,
+    // Output types of the method, e.g. ["(address,uint256,uint256)[]"].
+    pub outputs: Vec<String>,
+    // Which output is the list of records.
+    #[serde(default)]
+    pub output: usize,
+    // Which field of a record (tuple) is its owner. Ignored for lists of addresses.
+    #[serde(default)]
+    pub owner_field: usize,
+    #[serde(default)]
+    pub action: FilterAction,
+}
+
+struct ParsedFilter {
+    outputs: Vec<ParamType>,
+    output: usize,
+    owner_field: usize,
+    action: FilterAction,
+}
+
+/// Response filters of the whitelist entries, per contract and selector.
+#[derive(Default)]
+pub struct ResponseFilters {
+    filters: RwLock<HashMap<Address, HashMap<String, ParsedFilter>>>,
+}
+
+impl ResponseFilters {
+    pub fn new(entries: &[WhitelistEntry]) -> eyre::Result<Self> {
+        let mut filters = HashMap::new();
+        for entry in entries {
+            let Some(response_filters) = entry
+                .methods
+                .as_ref()
+                .and_then(|methods| methods.response_filters.as_ref())
+            else {
+                continue;
+            };
+            let Some(address) = &entry.address else {
+                continue;
+            };
+            let address = Address::from_str(address)?;
+            let contract_filters: &mut HashMap<_, _> = filters.entry(address).or_default();
+            for filter in response_filters {
+                let outputs = filter
+                    .outputs
+                    .iter()
+                    .map(|output| Reader::read(output))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|err| {
+                        eyre::eyre!("Invalid outputs of {}: {}", filter.selector, err)
+                    })?;
+                if !matches!(outputs.get(filter.output), Some(ParamType::Array(_))) {
+                    eyre::bail!(
+                        "Output {} of {} is not a list",
+                        filter.output,
+                        filter.selector
+                    );
+                }
+                contract_filters.insert(
+                    filter.selector.clone(),
+                    ParsedFilter {
+                        outputs,
+                        output: filter.output,
+                        owner_field: filter.owner_field,
+                        action: filter.action,
+                    },
+                );
+            }
+        }
+        Ok(ResponseFilters {
+            filters: RwLock::new(filters),
+        })
+    }
+
+    /// Replaces the filters (when the whitelist changes at runtime).
+    pub fn replace(&self, other: ResponseFilters) {
+        *self.filters.write().unwrap() = other.filters.into_inner().unwrap();
+    }
+
+    /// Removes the records of other owners from the result of the call. `users` are
+    /// the caller's addresses (None without credentials). Records without an owner
+    /// (zero address) are kept for everyone.
+    ///
+    /// Results that don't match the declared outputs are denied, not passed through.
+    pub fn apply(
+        &self,
+        req: &CallRequest,
+        users: Option<&HashSet<Address>>,
+        result: Bytes,
+    ) -> Result<Bytes, Denial> {
+        let (Some(contract), Some(data)) = (req.to, req.data.as_ref()) else {
+            return Ok(result);
+        };
+        let filters = self.filters.read().unwrap();
+        let Some(filter) = data.0.get(..4).and_then(|selector| {
+            filters
+                .get(&contract)
+                .and_then(|filters| filters.get(&hex::encode(selector)))
+        }) else {
+            return Ok(result);
+        };
+
+        let mut outputs = ethabi::decode(&filter.outputs, &result.0)
+            .map_err(|_| Denial::ResponseFilterFailed { contract })?;
+        let (Token::Array(records), ParamType::Array(record_type)) =
+            (&mut outputs[filter.output], &filter.outputs[filter.output])
+        else {
+            return Err(Denial::ResponseFilterFailed { contract });
+        };
+
+        let is_own = |record: &Token| {
+            let owner = match record {
+                Token::Tuple(fields) => fields.get(filter.owner_field),
+                record => Some(record),
+            };
+            match owner {
+                // Records without an owner are public.
+                Some(Token::Address(owner)) => {
+                    owner.is_zero() || users.map_or(false, |users| users.contains(owner))
+                }
+                _ => false,
+            }
+        };
+        match filter.action {
+            FilterAction::Drop => records.retain(is_own),
+            FilterAction::Zero => {
+                for record in records.iter_mut() {
+                    if !is_own(record) {
+                        *record = zero(record_type);
+                    }
+                }
+            }
+        }
+        Ok(Bytes(ethabi::encode(&outputs)))
+    }
+}
+
+// Zero value of the given type.
+fn zero(param: &ParamType) -> Token {
+    match param {
+        ParamType::Address => Token::Address(Address::zero()),
+        ParamType::Bytes => Token::Bytes(vec![]),
+        ParamType::Int(_) => Token::Int(0.into()),
+        ParamType::Uint(_) => Token::Uint(0.into()),
+        ParamType::Bool => Token::Bool(false),
+        ParamType::String => Token::String(String::new()),
+        ParamType::Array(_) => Token::Array(vec![]),
+        ParamType::FixedBytes(size) => Token::FixedBytes(vec![0; *size]),
+        ParamType::FixedArray(param, size) => Token::FixedArray(vec![zero(param); *size]),
+        ParamType::Tuple(params) => Token::Tuple(params.iter().map(zero).collect()),
+    }
+}
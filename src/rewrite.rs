@@ -0,0 +1,80 @@
+// Rewriting of request bodies done by the auth middleware, and the table of methods it
+// rewrites. Depends only on serde_json, so that it can also be built into the fuzz target
+// (fuzz/fuzz_targets/rewrite_body.rs).
+
+use serde_json::{json, Value};
+
+/// Public methods whose result depends on an account, and their private versions (that take
+/// credentials as the first param). The auth middleware rewrites requests based on this table.
+pub const PRIVATE_METHODS: &[(&str, &str)] = &[
+    ("eth_blockNumber", "privateeth_blockNumber"),
+    ("eth_getBalance", "privateeth_getBalance"),
+    ("eth_call", "privateeth_call"),
+    ("eth_estimateGas", "privateeth_estimateGas"),
+    ("eth_getTransactionCount", "privateeth_getTransactionCount"),
+    ("eth_getStorageAt", "privateeth_getStorageAt"),
+    ("eth_getBlockByNumber", "privateeth_getBlockByNumber"),
+    ("eth_getBlockByHash", "privateeth_getBlockByHash"),
+    ("zks_estimateFee", "privateeth_estimateFee"),
+    ("eth_newFilter", "privateeth_newFilter"),
+    ("eth_newBlockFilter", "privateeth_newBlockFilter"),
+    ("eth_getFilterChanges", "privateeth_getFilterChanges"),
+    ("eth_uninstallFilter", "privateeth_uninstallFilter"),
+    (
+        "zks_getAllAccountBalances",
+        "privateeth_getAllAccountBalances",
+    ),
+];
+
+/// Renames calls of methods that have a private version (as returned by `private_method`)
+/// and inserts the credentials as their first param. Works for single calls and batches.
+///
+/// Returns None if nothing was rewritten - including bodies that are not valid JSON-RPC,
+/// which are left for the server to reject.
+pub fn rewrite_body(
+    body: &[u8],
+    credentials: &str,
+    private_method: impl Fn(&str) -> Option<&'static str>,
+) -> Option<Vec<u8>> {
+    let mut body: Value = serde_json::from_slice(body).ok()?;
+    let rewritten = match &mut body {
+        Value::Array(calls) => calls.iter_mut().fold(false, |rewritten, call| {
+            rewrite_call(call, credentials, &private_method) || rewritten
+        }),
+        call => rewrite_call(call, credentials, &private_method),
+    };
+    if rewritten {
+        serde_json::to_vec(&body).ok()
+    } else {
+        None
+    }
+}
+
+fn rewrite_call(
+    call: &mut Value,
+    credentials: &str,
+    private_method: &impl Fn(&str) -> Option<&'static str>,
+) -> bool {
+    let Some(call) = call.as_object_mut() else {
+        return false;
+    };
+    let Some(private) = call
+        .get("method")
+        .and_then(Value::as_str)
+        .and_then(private_method)
+    else {
+        return false;
+    };
+    // Credentials can only be prepended to positional params.
+    let params = match call.get("params") {
+        None | Some(Value::Null) => vec![],
+        Some(Value::Array(params)) => params.clone(),
+        Some(_) => return false,
+    };
+    call.insert("method".to_string(), json!(private));
+    call.insert(
+        "params".to_string(),
+        Value::Array(std::iter::once(json!(credentials)).chain(params).collect()),
+    );
+    true
+}
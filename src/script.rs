@@ -0,0 +1,267 @@
+// Per-contract rules written in Rhai, for cases the whitelist can't express
+// (e.g. "allow getPosition(id) only if ownerOf(id) is one of the caller's addresses").
+//
+// A whitelist entry can point to a script, which is run for calls to that contract. Scripts see:
+//  * `request` - map with `contract`, `from`, `selector` and `args` (32-byte words as hex, no '0x'),
+//  * `users` - the caller's addresses (empty without credentials),
+//  * `eth_call(to, data)` - read-only call to the sequencer, returns the result as hex,
+//  * `words(hex)` and `address(word)` - helpers to decode results,
+// and must end with `allow()` or `deny("reason")`. Scripts have no access to files or network,
+// and are stopped once they run out of time.
+
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use rhai::{
+    module_resolvers::DummyModuleResolver, Array, Dynamic, Engine, EvalAltResult, Map, Scope, AST,
+};
+use serde::Deserialize;
+use tokio::runtime::Handle;
+use zksync_types::{transaction_request::CallRequest, url::SensitiveUrl, web3::Bytes, Address};
+use zksync_web3_decl::{
+    client::{Client, L2},
+    jsonrpsee::core::async_trait,
+    namespaces::EthNamespaceClient,
+};
+
+use crate::config::WhitelistEntry;
+use crate::error::Denial;
+use crate::metrics::observe_upstream;
+use crate::policy::{Decision, Policy};
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ScriptConfig {
+    // Wall-clock limit of a single run (including its upstream calls).
+    #[serde(default = "ScriptConfig::default_time_limit_ms")]
+    pub time_limit_ms: u64,
+
+    // How many eth_calls a single run can make.
+    #[serde(default = "ScriptConfig::default_max_upstream_calls")]
+    pub max_upstream_calls: usize,
+
+    // Limit of Rhai operations per run.
+    #[serde(default = "ScriptConfig::default_max_operations")]
+    pub max_operations: u64,
+}
+
+impl ScriptConfig {
+    fn default_time_limit_ms() -> u64 {
+        200
+    }
+
+    fn default_max_upstream_calls() -> usize {
+        4
+    }
+
+    fn default_max_operations() -> u64 {
+        100_000
+    }
+}
+
+impl Default for ScriptConfig {
+    fn default() -> Self {
+        ScriptConfig {
+            time_limit_ms: ScriptConfig::default_time_limit_ms(),
+            max_upstream_calls: ScriptConfig::default_max_upstream_calls(),
+            max_operations: ScriptConfig::default_max_operations(),
+        }
+    }
+}
+
+// Result of a script - returned by `allow()` and `deny(reason)`.
+#[derive(Debug, Clone)]
+enum Verdict {
+    Allow,
+    Deny(String),
+}
+
+/// Runs the scripts of the whitelist entries. Abstains for contracts without a script.
+pub struct ScriptPolicy {
+    sequencer_url: String,
+    scripts: HashMap<Address, Arc<AST>>,
+    config: ScriptConfig,
+}
+
+impl ScriptPolicy {
+    /// Compiles the scripts referenced by the entries.
+    pub fn load(
+        sequencer_url: &str,
+        entries: &[WhitelistEntry],
+        config: ScriptConfig,
+    ) -> eyre::Result<Self> {
+        let engine = Engine::new();
+        let mut scripts = HashMap::new();
+        for entry in entries {
+            let Some(path) = &entry.script else {
+                continue;
+            };
+            let Some(address) = &entry.address else {
+                continue;
+            };
+            let address = Address::from_str(address)?;
+            let ast = engine
+                .compile_file(path.into())
+                .map_err(|err| eyre::eyre!("Unable to compile script {}: {}", path, err))?;
+            scripts.insert(address, Arc::new(ast));
+        }
+        Ok(ScriptPolicy {
+            sequencer_url: sequencer_url.to_string(),
+            scripts,
+            config,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scripts.is_empty()
+    }
+
+    pub fn create_client(&self) -> Client<L2> {
+        let url = SensitiveUrl::from_str(&self.sequencer_url)
+            .unwrap_or_else(|_| panic!("Unable to parse client URL: {}", &self.sequencer_url));
+        Client::http(url)
+            .unwrap_or_else(|_| {
+                panic!("Unable to create a client for fork: {}", self.sequencer_url)
+            })
+            .build()
+    }
+
+    // Sandboxed engine for a single run (upstream calls are counted per run).
+    fn engine(&self, handle: Handle, deadline: Instant) -> Engine {
+        let mut engine = Engine::new();
+        engine.set_module_resolver(DummyModuleResolver::new());
+        engine.set_max_operations(self.config.max_operations);
+        engine.set_max_call_levels(32);
+        engine.set_max_string_size(64 * 1024);
+        engine.set_max_array_size(1024);
+        engine.on_progress(move |_| (Instant::now() > deadline).then_some(Dynamic::UNIT));
+        engine.on_print(|text| tracing::debug!("Script: {}", text));
+        engine.on_debug(|text, _, _| tracing::debug!("Script: {}", text));
+
+        engine.register_type_with_name::<Verdict>("Verdict");
+        engine.register_fn("allow", || Verdict::Allow);
+        engine.register_fn("deny", |reason: &str| Verdict::Deny(reason.to_string()));
+        engine.register_fn("words", |data: &str| words_of_hex(data));
+        engine.register_fn("address", address_of_word);
+
+        let client = Arc::new(self.create_client());
+        let calls = Arc::new(AtomicUsize::new(0));
+        let max_calls = self.config.max_upstream_calls;
+        engine.register_fn(
+            "eth_call",
+            move |to: &str, data: &str| -> Result<String, Box<EvalAltResult>> {
+                if calls.fetch_add(1, Ordering::Relaxed) >= max_calls {
+                    return Err("too many eth_calls".into());
+                }
+                let mut req = CallRequest::default();
+                req.to = Some(Address::from_str(to).map_err(|_| "invalid address")?);
+                req.data = Some(Bytes(
+                    hex::decode(data.trim_start_matches("0x")).map_err(|_| "invalid calldata")?,
+                ));
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                let result = handle
+                    .block_on(tokio::time::timeout(
+                        remaining,
+                        observe_upstream("eth_call", client.call(req, None)),
+                    ))
+                    .map_err(|_| "eth_call timed out")?
+                    .map_err(|err| format!("eth_call failed: {}", err))?;
+                Ok(format!("0x{}", hex::encode(result.0)))
+            },
+        );
+        engine
+    }
+}
+
+// Splits the data into 32-byte words (as hex without '0x') - the last one is zero padded.
+fn words(data: &[u8]) -> Array {
+    data.chunks(32)
+        .map(|word| {
+            let mut padded = [0u8; 32];
+            padded[..word.len()].copy_from_slice(word);
+            Dynamic::from(hex::encode(padded))
+        })
+        .collect()
+}
+
+fn words_of_hex(data: &str) -> Result<Array, Box<EvalAltResult>> {
+    let data = hex::decode(data.trim_start_matches("0x")).map_err(|_| "invalid hex")?;
+    Ok(words(&data))
+}
+
+// Address in the last 20 bytes of the word - formatted the same way as `users`.
+fn address_of_word(word: &str) -> Result<String, Box<EvalAltResult>> {
+    let word = hex::decode(word.trim_start_matches("0x")).map_err(|_| "invalid hex")?;
+    if word.len() != 32 {
+        return Err("word must have 32 bytes".into());
+    }
+    Ok(format!("{:?}", Address::from_slice(&word[12..])))
+}
+
+#[async_trait]
+impl Policy for ScriptPolicy {
+    async fn evaluate_call(&self, req: &CallRequest, users: Option<&HashSet<Address>>) -> Decision {
+        let Some(contract) = req.to else {
+            return Decision::Abstain;
+        };
+        let Some(script) = self.scripts.get(&contract).cloned() else {
+            return Decision::Abstain;
+        };
+        // Calls without a selector are left to the whitelist.
+        let Some(data) = req.data.as_ref().filter(|data| data.0.len() >= 4) else {
+            return Decision::Abstain;
+        };
+
+        let mut call = Map::new();
+        call.insert("contract".into(), format!("{:?}", contract).into());
+        call.insert(
+            "from".into(),
+            req.from
+                .map(|from| format!("{:?}", from))
+                .unwrap_or_default()
+                .into(),
+        );
+        call.insert("selector".into(), hex::encode(&data.0[..4]).into());
+        call.insert("args".into(), words(&data.0[4..]).into());
+        let users: Array = users
+            .into_iter()
+            .flatten()
+            .map(|user| format!("{:?}", user).into())
+            .collect();
+
+        let time_limit = Duration::from_millis(self.config.time_limit_ms);
+        let engine = self.engine(Handle::current(), Instant::now() + time_limit);
+        // Blocking thread, so that eth_call can wait for the sequencer.
+        let run = tokio::task::spawn_blocking(move || {
+            let mut scope = Scope::new();
+            scope.push("request", call);
+            scope.push("users", users);
+            engine
+                .eval_ast_with_scope::<Dynamic>(&mut scope, &script)
+                .map_err(|err| err.to_string())?
+                .try_cast::<Verdict>()
+                .ok_or_else(|| "script must end with allow() or deny(reason)".to_string())
+        });
+
+        // The script checks the deadline itself - this also covers a stuck upstream call.
+        let result = match tokio::time::timeout(time_limit, run).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(err)) => Err(err.to_string()),
+            Err(_) => Err("timed out".to_string()),
+        };
+        match result {
+            Ok(Verdict::Allow) => Decision::Allow,
+            Ok(Verdict::Deny(reason)) => Decision::Deny(Denial::ScriptDenied { contract, reason }),
+            Err(err) => {
+                tracing::warn!(contract = ?contract, "Script failed: {}", err);
+                Decision::Deny(Denial::ScriptFailed { contract })
+            }
+        }
+    }
+}
@@ -1,29 +1,100 @@
 use std::collections::HashSet;
+use std::sync::Arc;
 use std::{collections::HashMap, ops::Add};
 
 use std::str::FromStr;
-use zksync_types::{protocol_upgrade::Call, transaction_request::CallRequest, Address};
+use zksync_types::{
+    api::Log, protocol_upgrade::Call, transaction_request::CallRequest, web3::Bytes, Address, H256,
+};
+use zksync_web3_decl::jsonrpsee::core::async_trait;
 
-use crate::WhitelistEntry;
+use crate::config::WhitelistEntry;
+use crate::error::Denial;
+use crate::instances::InstanceLookup;
+use crate::policy::{Decision, Policy};
 
 #[derive(Clone)]
 pub struct ContractWhitelist {
     whitelisted_contracts: HashMap<Address, WhitelistEntry>,
+    // Entries for every contract with the bytecode hash / deployed by the factory.
+    by_code_hash: HashMap<H256, WhitelistEntry>,
+    by_factory: HashMap<Address, WhitelistEntry>,
+    instances: Option<Arc<InstanceLookup>>,
 }
 
 impl ContractWhitelist {
-    pub fn init(contract_whitelist: Vec<WhitelistEntry>) -> Self {
-        ContractWhitelist {
-            whitelisted_contracts: HashMap::from_iter(
-                contract_whitelist
-                    .iter()
-                    .map(|a| (Address::from_str(&a.address).unwrap(), a.clone())),
-            ),
+    pub fn init(contract_whitelist: Vec<WhitelistEntry>) -> eyre::Result<Self> {
+        let mut whitelist = ContractWhitelist {
+            whitelisted_contracts: HashMap::new(),
+            by_code_hash: HashMap::new(),
+            by_factory: HashMap::new(),
+            instances: None,
+        };
+        for entry in contract_whitelist {
+            entry.validate()?;
+            if let Some(address) = &entry.address {
+                let address = Address::from_str(address)?;
+                whitelist.whitelisted_contracts.insert(address, entry);
+            } else if let Some(code_hash) = &entry.code_hash {
+                let code_hash = H256::from_str(code_hash)?;
+                whitelist.by_code_hash.insert(code_hash, entry);
+            } else if let Some(factory) = &entry.factory {
+                let factory = Address::from_str(factory)?;
+                whitelist.by_factory.insert(factory, entry);
+            }
+        }
+        Ok(whitelist)
+    }
+
+    /// Enables the entries matched by bytecode hash or factory - without the lookup,
+    /// only the entries with an address are used.
+    pub fn with_instances(mut self, instances: Arc<InstanceLookup>) -> Self {
+        self.instances = Some(instances);
+        self
+    }
+
+    // Entry of the contract - by its address, or else by its bytecode hash or factory.
+    // The contract is only looked up if one of those entries could allow the selector.
+    async fn entry(
+        &self,
+        contract: Address,
+        selector: Option<&str>,
+    ) -> Result<Option<&WhitelistEntry>, Denial> {
+        if let Some(entry) = self.whitelisted_contracts.get(&contract) {
+            return Ok(Some(entry));
         }
+        let Some(instances) = &self.instances else {
+            return Ok(None);
+        };
+        let allows_selector = |entry: &WhitelistEntry| {
+            entry.fully_whitelisted
+                || selector.map_or(false, |selector| entry.lists_selector(selector))
+        };
+        if !self
+            .by_code_hash
+            .values()
+            .chain(self.by_factory.values())
+            .any(allows_selector)
+        {
+            return Ok(None);
+        }
+        let instance = instances.instance(contract).await?;
+        Ok(instance
+            .code_hash
+            .and_then(|code_hash| self.by_code_hash.get(&code_hash))
+            .or_else(|| {
+                instance
+                    .deployer
+                    .and_then(|deployer| self.by_factory.get(&deployer))
+            }))
     }
 
+    // None if there is no calldata, or it is too short to contain a selector.
     fn get_selector(req: &CallRequest) -> Option<String> {
-        req.data.as_ref().map(|input| hex::encode(&input.0[..4]))
+        req.data
+            .as_ref()
+            .and_then(|input| input.0.get(..4))
+            .map(hex::encode)
     }
 
     fn get_first_user(req: &CallRequest) -> Option<Address> {
@@ -46,62 +117,167 @@ impl ContractWhitelist {
         Some(Address::from_slice(first_param))
     }
 
-    pub fn allow_unauthorized_call(&self, req: &CallRequest) -> bool {
-        if let Some(to) = req.to {
-            // Contract must be on the whitelist
-            match self.whitelisted_contracts.get(&to) {
-                Some(whitelist_entry) => {
-                    if whitelist_entry.fully_whitelisted {
-                        true
-                    } else {
-                        let selector = ContractWhitelist::get_selector(req);
-                        match selector {
-                            Some(selector) => {
-                                println!("looking at selector {}", selector);
-                                match &whitelist_entry.methods {
-                                    Some(methods) => match &methods.unrestricted {
-                                        Some(unrestricted) => unrestricted.contains(&selector),
-                                        None => false,
-                                    },
-                                    None => false,
-                                }
-                            }
-                            None => false,
-                        }
-                    }
-                }
-                None => false,
-            }
+    /// Checks the call against the entries with an address (see `evaluate_call` for all of them).
+    pub fn allow_unauthorized_call(&self, req: &CallRequest) -> Result<(), Denial> {
+        let entry = req.to.and_then(|to| self.whitelisted_contracts.get(&to));
+        ContractWhitelist::check_unauthorized_call(req, entry)
+    }
+
+    fn check_unauthorized_call(
+        req: &CallRequest,
+        entry: Option<&WhitelistEntry>,
+    ) -> Result<(), Denial> {
+        // Calls to 'null' address (eth contract creation) not allowed.
+        let to = req.to.ok_or(Denial::ContractCreation)?;
+
+        // Contract must be on the whitelist
+        let whitelist_entry = entry.ok_or(Denial::ContractNotWhitelisted { contract: to })?;
+
+        if whitelist_entry.fully_whitelisted {
+            return Ok(());
+        }
+
+        let selector =
+            ContractWhitelist::get_selector(req).ok_or(Denial::MissingSelector { contract: to })?;
+        tracing::debug!(contract = ?to, selector = %selector, "Checking selector");
+        let allowed = whitelist_entry
+            .methods
+            .as_ref()
+            .and_then(|methods| methods.unrestricted.as_ref())
+            .map(|unrestricted| unrestricted.contains(&selector))
+            .unwrap_or(false);
+        if allowed {
+            Ok(())
         } else {
-            // Calls to 'null' address (eth contract creation) not allowed.
-            false
+            Err(Denial::SelectorNotAllowed {
+                contract: to,
+                selector,
+            })
         }
     }
 
-    pub fn allow_authorized_call(&self, req: &CallRequest, users: &HashSet<Address>) -> bool {
-        if self.allow_unauthorized_call(req) {
-            return true;
-        }
+    pub fn allow_authorized_call(
+        &self,
+        req: &CallRequest,
+        users: &HashSet<Address>,
+    ) -> Result<(), Denial> {
+        let entry = req.to.and_then(|to| self.whitelisted_contracts.get(&to));
+        ContractWhitelist::check_authorized_call(req, entry, users)
+    }
+
+    fn check_authorized_call(
+        req: &CallRequest,
+        entry: Option<&WhitelistEntry>,
+        users: &HashSet<Address>,
+    ) -> Result<(), Denial> {
+        let unauthorized_denial = match ContractWhitelist::check_unauthorized_call(req, entry) {
+            Ok(()) => return Ok(()),
+            Err(denial) => denial,
+        };
 
         if let Some(req_user) = ContractWhitelist::get_first_user(req) {
             if !users.contains(&req_user) {
-                println!("User {} not in hashset", req_user);
-                return false;
+                tracing::debug!(user = ?req_user, "User is not authorized for these credentials");
+                return Err(Denial::AddressNotAuthorized { address: req_user });
             }
 
-            if let Some(to) = req.to {
-                if let Some(whitelist) = self.whitelisted_contracts.get(&to) {
-                    if let Some(selector) = ContractWhitelist::get_selector(req) {
-                        if let Some(methods) = &whitelist.methods {
-                            if let Some(authorizations) = &methods.requires_authorization {
-                                // We need more verification here..
-                                return authorizations.contains(&selector);
+            if let Some(whitelist) = entry {
+                if let Some(selector) = ContractWhitelist::get_selector(req) {
+                    if let Some(methods) = &whitelist.methods {
+                        if let Some(authorizations) = &methods.requires_authorization {
+                            // We need more verification here..
+                            if authorizations.contains(&selector) {
+                                return Ok(());
                             }
                         }
                     }
                 }
             }
         }
-        return false;
+        Err(unauthorized_denial)
+    }
+
+    // Address encoded in an indexed event param (left padded with zeros to 32 bytes).
+    fn topic_address(topic: &H256) -> Option<Address> {
+        if topic.0[..12].iter().any(|byte| *byte != 0) {
+            return None;
+        }
+        Some(Address::from_slice(&topic.0[12..]))
+    }
+
+    /// Logs are public only if they come from a fully whitelisted contract (listed by address).
+    /// Entries matched by code hash or factory don't apply - that would take a lookup for
+    /// every contract that emitted one of the logs.
+    pub fn allow_unauthorized_log(&self, log: &Log) -> bool {
+        self.whitelisted_contracts
+            .get(&log.address)
+            .map(|entry| entry.fully_whitelisted)
+            .unwrap_or(false)
+    }
+
+    /// Logs of other whitelisted contracts are visible only if one of the indexed params
+    /// (e.g. 'from' or 'to' of a Transfer) is one of the users.
+    pub fn allow_authorized_log(&self, log: &Log, users: &HashSet<Address>) -> bool {
+        if self.allow_unauthorized_log(log) {
+            return true;
+        }
+        if !self.whitelisted_contracts.contains_key(&log.address) {
+            return false;
+        }
+        // First topic is the event signature.
+        log.topics
+            .iter()
+            .skip(1)
+            .filter_map(ContractWhitelist::topic_address)
+            .any(|address| users.contains(&address))
+    }
+}
+
+/// The whitelist from the config. Allows any transaction and any method that the proxy
+/// serves (disabled methods are denied by the proxy itself).
+#[async_trait]
+impl Policy for ContractWhitelist {
+    async fn evaluate_call(&self, req: &CallRequest, users: Option<&HashSet<Address>>) -> Decision {
+        let entry = match req.to {
+            Some(to) => match self
+                .entry(to, ContractWhitelist::get_selector(req).as_deref())
+                .await
+            {
+                Ok(entry) => entry,
+                Err(denial) => return Decision::Deny(denial),
+            },
+            None => None,
+        };
+        match users {
+            Some(users) => ContractWhitelist::check_authorized_call(req, entry, users),
+            None => ContractWhitelist::check_unauthorized_call(req, entry),
+        }
+        .into()
+    }
+
+    fn evaluate_tx(&self, _tx: &Bytes) -> Decision {
+        Decision::Allow
+    }
+
+    fn evaluate_log(&self, log: &Log, users: Option<&HashSet<Address>>) -> Decision {
+        let allowed = match users {
+            Some(users) => self.allow_authorized_log(log, users),
+            None => self.allow_unauthorized_log(log),
+        };
+        if allowed {
+            Decision::Allow
+        } else {
+            Decision::Deny(Denial::ContractNotWhitelisted {
+                contract: log.address,
+            })
+        }
+    }
+
+    fn evaluate_method(
+        &self,
+        _method: &'static str,
+        _users: Option<&HashSet<Address>>,
+    ) -> Decision {
+        Decision::Allow
     }
 }
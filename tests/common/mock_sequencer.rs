@@ -0,0 +1,172 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use serde_json::{json, Value};
+use zksync_web3_decl::jsonrpsee::{
+    server::{ServerBuilder, ServerHandle},
+    types::{ErrorObject, ErrorObjectOwned},
+    RpcModule,
+};
+
+/// Call received by the mock sequencer.
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub params: Value,
+}
+
+type Requests = Arc<Mutex<Vec<RecordedRequest>>>;
+
+/// Scripted responses of the mock sequencer, per method. Methods that were not scripted
+/// answer with 'method not found' (and are not recorded).
+pub struct MockSequencerBuilder {
+    // Returned one after another - the last one then for all the remaining calls.
+    responses: HashMap<&'static str, Vec<Result<Value, ErrorObjectOwned>>>,
+}
+
+impl MockSequencerBuilder {
+    pub fn respond(mut self, method: &'static str, result: Value) -> Self {
+        self.responses.insert(method, vec![Ok(result)]);
+        self
+    }
+
+    /// Responds with the results in order (e.g. a receipt before and after its batch seals).
+    pub fn respond_in_order(mut self, method: &'static str, results: Vec<Value>) -> Self {
+        self.responses
+            .insert(method, results.into_iter().map(Ok).collect());
+        self
+    }
+
+    pub fn fail(mut self, method: &'static str, code: i32, message: &str) -> Self {
+        self.responses.insert(
+            method,
+            vec![Err(ErrorObject::owned(
+                code,
+                message.to_string(),
+                None::<()>,
+            ))],
+        );
+        self
+    }
+
+    pub async fn start(self) -> MockSequencer {
+        let requests = Requests::default();
+        let mut module = RpcModule::new(requests.clone());
+        for (method, responses) in self.responses {
+            module
+                .register_method(method, move |params, requests: &Requests| {
+                    let mut requests = requests.lock().unwrap();
+                    let calls = requests
+                        .iter()
+                        .filter(|request| request.method == method)
+                        .count();
+                    requests.push(RecordedRequest {
+                        method: method.to_string(),
+                        params: params.parse::<Value>().unwrap_or(Value::Null),
+                    });
+                    responses[calls.min(responses.len() - 1)].clone()
+                })
+                .unwrap();
+        }
+
+        let server = ServerBuilder::default()
+            .http_only()
+            .build("127.0.0.1:0")
+            .await
+            .unwrap();
+        let url = format!("http://{}", server.local_addr().unwrap());
+        MockSequencer {
+            url,
+            requests,
+            _handle: server.start(module),
+        }
+    }
+}
+
+/// In-process JSON-RPC server that stands in for the sequencer.
+pub struct MockSequencer {
+    pub url: String,
+    requests: Requests,
+    // Server stops once the handle is dropped.
+    _handle: ServerHandle,
+}
+
+impl MockSequencer {
+    /// Every mock answers eth_chainId, which the proxy fetches for net_version.
+    pub fn builder() -> MockSequencerBuilder {
+        MockSequencerBuilder {
+            responses: HashMap::new(),
+        }
+        .respond("eth_chainId", json!("0x104"))
+    }
+
+    /// All calls of `method` that reached the sequencer.
+    pub fn requests(&self, method: &str) -> Vec<RecordedRequest> {
+        self.requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|request| request.method == method)
+            .cloned()
+            .collect()
+    }
+}
+
+/// Block as returned by the sequencer, with the given transaction hashes.
+pub fn block_json(number: u64, transactions: &[&str]) -> Value {
+    let zero_hash = format!("0x{}", "00".repeat(32));
+    json!({
+        "hash": format!("0x{:064x}", number + 1),
+        "parentHash": format!("0x{:064x}", number),
+        "sha3Uncles": zero_hash,
+        "miner": "0x0000000000000000000000000000000000000000",
+        "stateRoot": zero_hash,
+        "transactionsRoot": zero_hash,
+        "receiptsRoot": zero_hash,
+        "number": format!("{:#x}", number),
+        "l1BatchNumber": "0x1",
+        "gasUsed": "0x1234567",
+        "gasLimit": "0x4000000000000",
+        "baseFeePerGas": "0xee6b280",
+        "extraData": "0x",
+        "logsBloom": format!("0x{}", "ff".repeat(256)),
+        "timestamp": "0x6650f000",
+        "l1BatchTimestamp": "0x6650f000",
+        "difficulty": "0x0",
+        "totalDifficulty": "0x0",
+        "sealFields": [],
+        "uncles": [],
+        "transactions": transactions,
+        "size": "0x400",
+        "mixHash": zero_hash,
+        "nonce": "0x0000000000000000",
+    })
+}
+
+/// Receipt of a successful transaction in the given block - `l1_batch` is None until the
+/// batch is sealed.
+pub fn receipt_json(hash: &str, block: u64, l1_batch: Option<u64>) -> Value {
+    let zero_hash = format!("0x{}", "00".repeat(32));
+    json!({
+        "transactionHash": hash,
+        "transactionIndex": "0x0",
+        "blockHash": format!("0x{:064x}", block + 1),
+        "blockNumber": format!("{:#x}", block),
+        "l1BatchTxIndex": l1_batch.map(|_| "0x0"),
+        "l1BatchNumber": l1_batch.map(|batch| format!("{:#x}", batch)),
+        "from": "0x0000000000000000000000000000000000000001",
+        "to": "0x111C3E89Ce80e62EE88318C2804920D4c96f92bb",
+        "cumulativeGasUsed": "0x0",
+        "gasUsed": "0x5208",
+        "effectiveGasPrice": "0xee6b280",
+        "contractAddress": null,
+        "logs": [],
+        "l2ToL1Logs": [],
+        "status": "0x1",
+        "root": zero_hash,
+        "logsBloom": format!("0x{}", "00".repeat(256)),
+        "type": "0x2",
+    })
+}
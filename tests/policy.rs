@@ -0,0 +1,81 @@
+use std::{collections::HashSet, sync::Arc};
+
+use doubleo::error::Denial;
+use doubleo::policy::{AllOf, AnyOf, Decision, DenyOverrides, Policy};
+use zksync_types::Address;
+
+// Policy that gives the same decision for every method.
+struct Fixed(Decision);
+
+impl Policy for Fixed {
+    fn evaluate_method(
+        &self,
+        _method: &'static str,
+        _users: Option<&HashSet<Address>>,
+    ) -> Decision {
+        self.0.clone()
+    }
+}
+
+fn allow() -> Arc<dyn Policy> {
+    Arc::new(Fixed(Decision::Allow))
+}
+
+fn deny(method: &'static str) -> Arc<dyn Policy> {
+    Arc::new(Fixed(Decision::Deny(Denial::MethodNotAllowed { method })))
+}
+
+fn abstain() -> Arc<dyn Policy> {
+    Arc::new(Fixed(Decision::Abstain))
+}
+
+fn evaluate(policy: impl Policy) -> Decision {
+    policy.evaluate_method("eth_call", None)
+}
+
+fn denied_by(method: &'static str) -> Decision {
+    Decision::Deny(Denial::MethodNotAllowed { method })
+}
+
+#[test]
+fn all_of_needs_every_policy() {
+    assert_eq!(evaluate(AllOf(vec![allow(), allow()])), Decision::Allow);
+    assert_eq!(evaluate(AllOf(vec![allow(), deny("a")])), denied_by("a"));
+    assert_eq!(evaluate(AllOf(vec![allow(), abstain()])), Decision::Abstain);
+    assert_eq!(
+        evaluate(AllOf(vec![abstain(), deny("a"), deny("b")])),
+        denied_by("a")
+    );
+}
+
+#[test]
+fn any_of_needs_one_policy() {
+    assert_eq!(evaluate(AnyOf(vec![deny("a"), allow()])), Decision::Allow);
+    assert_eq!(
+        evaluate(AnyOf(vec![abstain(), deny("a"), deny("b")])),
+        denied_by("a")
+    );
+    assert_eq!(evaluate(AnyOf(vec![abstain()])), Decision::Abstain);
+}
+
+#[test]
+fn deny_overrides_ignores_abstains() {
+    assert_eq!(
+        evaluate(DenyOverrides(vec![allow(), abstain()])),
+        Decision::Allow
+    );
+    assert_eq!(
+        evaluate(DenyOverrides(vec![allow(), deny("a")])),
+        denied_by("a")
+    );
+    assert_eq!(evaluate(DenyOverrides(vec![abstain()])), Decision::Abstain);
+}
+
+#[test]
+fn abstain_is_denied() {
+    assert_eq!(
+        Decision::Abstain.into_result(),
+        Err(Denial::NotAllowedByPolicy)
+    );
+    assert!(evaluate(AllOf(vec![])).is_allowed());
+}
@@ -0,0 +1,1105 @@
+use serde_json::{json, Value};
+use zksync_web3_decl::jsonrpsee::{
+    core::{client::ClientT, ClientError},
+    http_client::{HttpClient, HttpClientBuilder},
+    rpc_params,
+    types::{error::ErrorCode, ErrorObjectOwned},
+    ws_client::WsClientBuilder,
+};
+
+mod common;
+
+use common::mock_sequencer::{block_json, receipt_json, MockSequencer};
+use doubleo::admin::MAINTENANCE_CODE;
+use doubleo::filters::FILTER_NOT_FOUND_CODE;
+use doubleo::rate_limit::RATE_LIMITED_CODE;
+use doubleo::{Config, ProxyBuilder, RunningProxy};
+
+const TEST_CONFIG: &str = r#"
+allow_contract_creation: false
+verbose_errors: true
+whitelist:
+  - address: "0x111C3E89Ce80e62EE88318C2804920D4c96f92bb"
+    fully_whitelisted: true
+  - address: "0x4B5DF730c2e6b28E17013A1485E5d9BC41Efe021"
+    fully_whitelisted: false
+    methods:
+      unrestricted:
+        - "18160ddd"
+      requires_authorization:
+        - "70a08231"
+  - address: "0x2dd6f8d2c2c03f2fff8ed1c9f3e7c1a1bb3a1d07"
+    fully_whitelisted: false
+    script: "scripts/owner_only.rhai"
+  - address: "0x5c0a3b5e7d9b4d2f8e1c6a4b3d2e1f0a9b8c7d6e"
+    fully_whitelisted: false
+    methods:
+      owner_lookup:
+        # getPosition(uint256) - owner from ownerOf(uint256)
+        - selector: "eb02c301"
+          lookup: "6352211e"
+  - address: "0x7a3f1c5e9b2d4f6a8c0e1b3d5f7a9c2e4b6d8f01"
+    fully_whitelisted: false
+    methods:
+      unrestricted:
+        - "7bea0d1c"
+      response_filters:
+        # getAllOrders() returns (owner, amount)[]
+        - selector: "7bea0d1c"
+          outputs: ["(address,uint256)[]"]
+"#;
+
+const PUBLIC_CONTRACT: &str = "0x111c3e89ce80e62ee88318c2804920d4c96f92bb";
+const TOKEN_CONTRACT: &str = "0x4b5df730c2e6b28e17013a1485e5d9bc41efe021";
+const NFT_CONTRACT: &str = "0x2dd6f8d2c2c03f2fff8ed1c9f3e7c1a1bb3a1d07";
+const POSITIONS_CONTRACT: &str = "0x5c0a3b5e7d9b4d2f8e1c6a4b3d2e1f0a9b8c7d6e";
+const ORDERS_CONTRACT: &str = "0x7a3f1c5e9b2d4f6a8c0e1b3d5f7a9c2e4b6d8f01";
+const USER: &str = "0x36615cf349d7f6344891b1e7ca7c72883f5dc049";
+const OTHER_USER: &str = "0xa61464658afeaf65cccaafd3a512b69a83b77618";
+
+async fn start_proxy(sequencer: &MockSequencer) -> RunningProxy {
+    start_proxy_with(sequencer, TEST_CONFIG).await
+}
+
+async fn start_proxy_with(sequencer: &MockSequencer, config: &str) -> RunningProxy {
+    let config: Config = serde_yaml::from_str(config).unwrap();
+    ProxyBuilder::new(sequencer.url.clone(), config)
+        .build()
+        .await
+        .unwrap()
+        .start("127.0.0.1:0")
+        .await
+        .unwrap()
+}
+
+// Client that passes the credentials in the url path (like the frontend does).
+fn client(proxy: &RunningProxy, credentials: Option<&str>) -> HttpClient {
+    let url = format!("http://{}/{}", proxy.local_addr, credentials.unwrap_or(""));
+    HttpClientBuilder::default().build(url).unwrap()
+}
+
+fn call_error(err: ClientError) -> ErrorObjectOwned {
+    match err {
+        ClientError::Call(err) => err,
+        err => panic!("Expected a JSON-RPC error, got {:?}", err),
+    }
+}
+
+fn denial_reason(err: ClientError) -> String {
+    let err = call_error(err);
+    assert_eq!(err.code(), 403);
+    let data: Value = serde_json::from_str(err.data().unwrap().get()).unwrap();
+    data["reason"].as_str().unwrap().to_string()
+}
+
+// balanceOf(address)
+fn balance_of(address: &str) -> String {
+    format!("0x70a08231000000000000000000000000{}", &address[2..])
+}
+
+// ABI encoded (address,uint256)[] - the amount of each order is its index.
+fn orders(owners: &[&str]) -> String {
+    let mut encoded = format!("0x{:064x}{:064x}", 0x20, owners.len());
+    for (index, owner) in owners.iter().enumerate() {
+        encoded += &format!("{:0>64}{:064x}", &owner[2..], index);
+    }
+    encoded
+}
+
+async fn add_credential(proxy: &RunningProxy, credentials: &str, address: &str) {
+    let added: bool = client(proxy, None)
+        .request(
+            "privateeth_addCredential",
+            rpc_params![credentials, address, "signature"],
+        )
+        .await
+        .unwrap();
+    assert!(added);
+}
+
+#[tokio::test]
+async fn forwards_call_to_whitelisted_contract() {
+    let sequencer = MockSequencer::builder()
+        .respond("eth_call", json!("0x2a"))
+        .start()
+        .await;
+    let proxy = start_proxy(&sequencer).await;
+
+    let result: Value = client(&proxy, None)
+        .request(
+            "eth_call",
+            rpc_params![
+                json!({"to": PUBLIC_CONTRACT, "data": "0x18160ddd"}),
+                "latest"
+            ],
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(result, json!("0x2a"));
+    let calls = sequencer.requests("eth_call");
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0].params[0]["to"], json!(PUBLIC_CONTRACT));
+}
+
+#[tokio::test]
+async fn denies_call_to_unknown_contract() {
+    let sequencer = MockSequencer::builder()
+        .respond("eth_call", json!("0x2a"))
+        .start()
+        .await;
+    let proxy = start_proxy(&sequencer).await;
+
+    let err = client(&proxy, None)
+        .request::<Value, _>(
+            "eth_call",
+            rpc_params![json!({"to": OTHER_USER, "data": "0x18160ddd"}), "latest"],
+        )
+        .await
+        .unwrap_err();
+
+    assert_eq!(denial_reason(err), "contract_not_whitelisted");
+    assert!(sequencer.requests("eth_call").is_empty());
+}
+
+#[tokio::test]
+async fn balance_requires_credentials() {
+    let sequencer = MockSequencer::builder()
+        .respond("eth_getBalance", json!("0x64"))
+        .start()
+        .await;
+    let proxy = start_proxy(&sequencer).await;
+
+    let err = client(&proxy, None)
+        .request::<Value, _>("eth_getBalance", rpc_params![USER, "latest"])
+        .await
+        .unwrap_err();
+
+    assert_eq!(denial_reason(err), "method_not_allowed");
+    assert!(sequencer.requests("eth_getBalance").is_empty());
+}
+
+#[tokio::test]
+async fn credentials_unlock_balance_of_bound_address() {
+    let sequencer = MockSequencer::builder()
+        .respond("eth_getBalance", json!("0x64"))
+        .start()
+        .await;
+    let proxy = start_proxy(&sequencer).await;
+    add_credential(&proxy, "abcd", USER).await;
+    let client = client(&proxy, Some("abcd"));
+
+    let balance: Value = client
+        .request("eth_getBalance", rpc_params![USER, "latest"])
+        .await
+        .unwrap();
+    assert_eq!(balance, json!("0x64"));
+
+    let err = client
+        .request::<Value, _>("eth_getBalance", rpc_params![OTHER_USER, "latest"])
+        .await
+        .unwrap_err();
+    assert_eq!(denial_reason(err), "address_not_authorized");
+
+    // Credentials are consumed by the proxy - the sequencer gets a plain eth_getBalance.
+    let calls = sequencer.requests("eth_getBalance");
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0].params, json!([USER, "latest"]));
+}
+
+#[tokio::test]
+async fn authorized_method_needs_bound_address() {
+    let sequencer = MockSequencer::builder()
+        .respond("eth_call", json!("0x64"))
+        .start()
+        .await;
+    let proxy = start_proxy(&sequencer).await;
+    add_credential(&proxy, "abcd", USER).await;
+
+    let unauthenticated = client(&proxy, None)
+        .request::<Value, _>(
+            "eth_call",
+            rpc_params![
+                json!({"to": TOKEN_CONTRACT, "data": balance_of(USER)}),
+                "latest"
+            ],
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(denial_reason(unauthenticated), "selector_not_allowed");
+
+    let client = client(&proxy, Some("abcd"));
+    let result: Value = client
+        .request(
+            "eth_call",
+            rpc_params![
+                json!({"to": TOKEN_CONTRACT, "data": balance_of(USER)}),
+                "latest"
+            ],
+        )
+        .await
+        .unwrap();
+    assert_eq!(result, json!("0x64"));
+
+    let err = client
+        .request::<Value, _>(
+            "eth_call",
+            rpc_params![
+                json!({"to": TOKEN_CONTRACT, "data": balance_of(OTHER_USER)}),
+                "latest"
+            ],
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(denial_reason(err), "address_not_authorized");
+
+    assert_eq!(sequencer.requests("eth_call").len(), 1);
+}
+
+#[tokio::test]
+async fn strips_transactions_from_blocks() {
+    let tx_hash = format!("0x{}", "ab".repeat(32));
+    let sequencer = MockSequencer::builder()
+        .respond("eth_getBlockByNumber", block_json(7, &[&tx_hash]))
+        .start()
+        .await;
+    let proxy = start_proxy(&sequencer).await;
+
+    let block: Value = client(&proxy, None)
+        .request("eth_getBlockByNumber", rpc_params!["0x7", false])
+        .await
+        .unwrap();
+
+    assert_eq!(block["number"], json!("0x7"));
+    assert_eq!(block["transactions"], json!([]));
+    assert_eq!(sequencer.requests("eth_getBlockByNumber").len(), 1);
+}
+
+#[tokio::test]
+async fn fee_history_redacts_gas_used_ratio() {
+    let sequencer = MockSequencer::builder()
+        .respond(
+            "eth_feeHistory",
+            json!({
+                "oldestBlock": "0x7",
+                "baseFeePerGas": ["0xee6b280", "0xee6b280"],
+                "gasUsedRatio": [0.1234],
+                "reward": null,
+            }),
+        )
+        .start()
+        .await;
+    for (redaction, ratio) in [("round", 0.1), ("hide", 0.0)] {
+        let config = format!(
+            "{}block_redaction:\n  gas_used: {}\n  gas_used_ratio_rounding: 0.1\n",
+            TEST_CONFIG, redaction
+        );
+        let proxy = start_proxy_with(&sequencer, &config).await;
+        let history: Value = client(&proxy, None)
+            .request(
+                "eth_feeHistory",
+                rpc_params!["0x1", "0x7", Vec::<f32>::new()],
+            )
+            .await
+            .unwrap();
+        assert_eq!(history["gasUsedRatio"], json!([ratio]));
+    }
+}
+
+#[tokio::test]
+async fn receipts_are_cached_once_their_batch_is_sealed() {
+    let hash = format!("0x{:064x}", 7);
+    let sequencer = MockSequencer::builder()
+        .respond_in_order(
+            "eth_getTransactionReceipt",
+            vec![
+                receipt_json(&hash, 16, None),
+                receipt_json(&hash, 16, Some(3)),
+            ],
+        )
+        .start()
+        .await;
+    let proxy = start_proxy(&sequencer).await;
+
+    let mut batches = vec![];
+    for _ in 0..3 {
+        let receipt: Value = client(&proxy, None)
+            .request("eth_getTransactionReceipt", rpc_params![&hash])
+            .await
+            .unwrap();
+        batches.push(receipt["l1BatchNumber"].clone());
+    }
+    assert_eq!(batches, vec![Value::Null, json!("0x3"), json!("0x3")]);
+    assert_eq!(sequencer.requests("eth_getTransactionReceipt").len(), 2);
+}
+
+#[tokio::test]
+async fn passes_sequencer_errors_through() {
+    let sequencer = MockSequencer::builder()
+        .fail("eth_gasPrice", -32603, "internal error")
+        .start()
+        .await;
+    let proxy = start_proxy(&sequencer).await;
+
+    let err = client(&proxy, None)
+        .request::<Value, _>("eth_gasPrice", rpc_params![])
+        .await
+        .unwrap_err();
+
+    // Sequencer errors are passed through as they are.
+    let err = call_error(err);
+    assert_eq!(err.code(), -32603);
+    assert_eq!(err.message(), "internal error");
+}
+
+#[tokio::test]
+async fn build_rejects_malformed_sequencer_url() {
+    let config: Config = serde_yaml::from_str(TEST_CONFIG).unwrap();
+    let result = ProxyBuilder::new("not a url".to_string(), config)
+        .build()
+        .await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn starts_before_sequencer_and_fetches_chain_id_once() {
+    // Nothing listens there.
+    let config: Config = serde_yaml::from_str(TEST_CONFIG).unwrap();
+    let proxy = ProxyBuilder::new("http://127.0.0.1:1".to_string(), config)
+        .build()
+        .await
+        .unwrap()
+        .start("127.0.0.1:0")
+        .await
+        .unwrap();
+    client(&proxy, None)
+        .request::<String, _>("net_version", rpc_params![])
+        .await
+        .unwrap_err();
+
+    let sequencer = MockSequencer::builder().start().await;
+    let proxy = start_proxy(&sequencer).await;
+    for _ in 0..2 {
+        let version: String = client(&proxy, None)
+            .request("net_version", rpc_params![])
+            .await
+            .unwrap();
+        assert_eq!(version, "260");
+    }
+    assert_eq!(sequencer.requests("eth_chainId").len(), 1);
+}
+
+#[tokio::test]
+async fn script_allows_calls_of_token_owner() {
+    // ownerOf (and the call itself) return USER.
+    let sequencer = MockSequencer::builder()
+        .respond("eth_call", json!(format!("0x{:0>64}", &USER[2..])))
+        .start()
+        .await;
+    let proxy = start_proxy(&sequencer).await;
+    add_credential(&proxy, "owner", USER).await;
+    add_credential(&proxy, "other", OTHER_USER).await;
+    // tokenURI(1)
+    let token_uri = json!({"to": NFT_CONTRACT, "data": format!("0xc87b56dd{:064x}", 1)});
+
+    let result: Value = client(&proxy, Some("owner"))
+        .request("eth_call", rpc_params![token_uri.clone(), "latest"])
+        .await
+        .unwrap();
+    assert_eq!(result, json!(format!("0x{:0>64}", &USER[2..])));
+
+    let err = client(&proxy, Some("other"))
+        .request::<Value, _>("eth_call", rpc_params![token_uri, "latest"])
+        .await
+        .unwrap_err();
+    assert_eq!(denial_reason(err), "script_denied");
+
+    // ownerOf(1) for each caller, plus the allowed call.
+    let calls = sequencer.requests("eth_call");
+    assert_eq!(calls.len(), 3);
+    assert_eq!(
+        calls[0].params[0]["data"],
+        json!(format!("0x6352211e{:064x}", 1))
+    );
+}
+
+#[tokio::test]
+async fn whitelist_methods_cannot_override_script() {
+    let sequencer = MockSequencer::builder().start().await;
+    // The script denies tokenURI to everyone but the owner - listing it would allow it to all.
+    let config = format!(
+        r#"{}
+  - address: "0x9f3c5a7e1b2d4f6a8c0e1b3d5f7a9c2e4b6d8f02"
+    fully_whitelisted: false
+    script: "scripts/owner_only.rhai"
+    methods:
+      unrestricted:
+        - "c87b56dd"
+"#,
+        TEST_CONFIG.trim_end()
+    );
+    let config: Config = serde_yaml::from_str(&config).unwrap();
+    assert!(ProxyBuilder::new(sequencer.url.clone(), config)
+        .build()
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn owner_lookup_allows_owner_and_is_cached() {
+    // ownerOf (and the call itself) return USER.
+    let sequencer = MockSequencer::builder()
+        .respond("eth_call", json!(format!("0x{:0>64}", &USER[2..])))
+        .start()
+        .await;
+    let proxy = start_proxy(&sequencer).await;
+    add_credential(&proxy, "owner", USER).await;
+    add_credential(&proxy, "other", OTHER_USER).await;
+    let get_position = json!({"to": POSITIONS_CONTRACT, "data": format!("0xeb02c301{:064x}", 7)});
+
+    for _ in 0..2 {
+        client(&proxy, Some("owner"))
+            .request::<Value, _>("eth_call", rpc_params![get_position.clone(), "latest"])
+            .await
+            .unwrap();
+    }
+    let err = client(&proxy, Some("other"))
+        .request::<Value, _>("eth_call", rpc_params![get_position.clone(), "latest"])
+        .await
+        .unwrap_err();
+    assert_eq!(denial_reason(err), "not_token_owner");
+
+    // Without credentials there is no one to compare the owner with.
+    let err = client(&proxy, None)
+        .request::<Value, _>("eth_call", rpc_params![get_position, "latest"])
+        .await
+        .unwrap_err();
+    assert_eq!(denial_reason(err), "selector_not_allowed");
+
+    // A single ownerOf(7) lookup, and the two allowed calls.
+    let calls = sequencer.requests("eth_call");
+    assert_eq!(calls.len(), 3);
+    assert_eq!(
+        calls[0].params[0]["data"],
+        json!(format!("0x6352211e{:064x}", 7))
+    );
+}
+
+#[tokio::test]
+async fn response_filter_returns_only_callers_records() {
+    let sequencer = MockSequencer::builder()
+        .respond("eth_call", json!(orders(&[USER, OTHER_USER])))
+        .start()
+        .await;
+    let proxy = start_proxy(&sequencer).await;
+    add_credential(&proxy, "owner", USER).await;
+    let get_all_orders = json!({"to": ORDERS_CONTRACT, "data": "0x7bea0d1c"});
+
+    let result: Value = client(&proxy, Some("owner"))
+        .request("eth_call", rpc_params![get_all_orders.clone(), "latest"])
+        .await
+        .unwrap();
+    assert_eq!(result, json!(orders(&[USER])));
+
+    let result: Value = client(&proxy, None)
+        .request("eth_call", rpc_params![get_all_orders, "latest"])
+        .await
+        .unwrap();
+    assert_eq!(result, json!(orders(&[])));
+}
+
+#[tokio::test]
+async fn public_estimation_needs_credential_for_sender() {
+    let sequencer = MockSequencer::builder()
+        .respond("eth_estimateGas", json!("0x5208"))
+        .start()
+        .await;
+    let proxy = start_proxy(&sequencer).await;
+    let transfer = |from: Option<&str>| {
+        let mut req = json!({"to": PUBLIC_CONTRACT, "data": "0x18160ddd"});
+        if let Some(from) = from {
+            req["from"] = json!(from);
+        }
+        req
+    };
+
+    let gas: Value = client(&proxy, None)
+        .request("eth_estimateGas", rpc_params![transfer(None)])
+        .await
+        .unwrap();
+    assert_eq!(gas, json!("0x5208"));
+
+    let err = client(&proxy, None)
+        .request::<Value, _>("eth_estimateGas", rpc_params![transfer(Some(USER))])
+        .await
+        .unwrap_err();
+    assert_eq!(denial_reason(err), "sender_requires_credential");
+
+    add_credential(&proxy, "owner", USER).await;
+    client(&proxy, Some("owner"))
+        .request::<Value, _>("eth_estimateGas", rpc_params![transfer(Some(USER))])
+        .await
+        .unwrap();
+    let err = client(&proxy, Some("owner"))
+        .request::<Value, _>("eth_estimateGas", rpc_params![transfer(Some(OTHER_USER))])
+        .await
+        .unwrap_err();
+    assert_eq!(denial_reason(err), "address_not_authorized");
+
+    assert_eq!(sequencer.requests("eth_estimateGas").len(), 2);
+}
+
+#[tokio::test]
+async fn public_estimation_scrubs_revert_reason() {
+    let sequencer = MockSequencer::builder()
+        .fail(
+            "eth_estimateGas",
+            3,
+            "execution reverted: balance of 0x36615cf3 is 17",
+        )
+        .start()
+        .await;
+    let proxy = start_proxy(&sequencer).await;
+
+    let err = client(&proxy, None)
+        .request::<Value, _>(
+            "eth_estimateGas",
+            rpc_params![json!({"to": PUBLIC_CONTRACT, "data": "0x18160ddd"})],
+        )
+        .await
+        .unwrap_err();
+    let err = call_error(err);
+    assert_eq!(err.code(), 3);
+    assert_eq!(err.message(), "execution reverted");
+}
+
+// Any contract with this code (32 zero bytes), or deployed by FACTORY.
+const INSTANCES_CONFIG: &str = r#"
+allow_contract_creation: false
+verbose_errors: true
+whitelist:
+  - code_hash: "0x01000001f862bd776c8fc18b8e9f8e20089714856ee233b3902a591d0d5f2925"
+    fully_whitelisted: false
+    methods:
+      unrestricted:
+        - "18160ddd"
+  - factory: "0x3f1c0de3a7b4c9e2d5f6a8b0c1e2d3f4a5b6c7d8"
+    fully_whitelisted: true
+"#;
+
+const FACTORY: &str = "0x3f1c0de3a7b4c9e2d5f6a8b0c1e2d3f4a5b6c7d8";
+const INSTANCE: &str = "0x9e8d7c6b5a4f3e2d1c0b9a8f7e6d5c4b3a2f1e0d";
+
+#[tokio::test]
+async fn instance_matched_by_code_hash_gets_entry_methods() {
+    let sequencer = MockSequencer::builder()
+        .respond("eth_getCode", json!(format!("0x{}", "00".repeat(32))))
+        .respond("eth_getLogs", json!([]))
+        .respond("eth_call", json!("0x2a"))
+        .start()
+        .await;
+    let proxy = start_proxy_with(&sequencer, INSTANCES_CONFIG).await;
+
+    for _ in 0..2 {
+        client(&proxy, None)
+            .request::<Value, _>(
+                "eth_call",
+                rpc_params![json!({"to": INSTANCE, "data": "0x18160ddd"}), "latest"],
+            )
+            .await
+            .unwrap();
+    }
+    let err = client(&proxy, None)
+        .request::<Value, _>(
+            "eth_call",
+            rpc_params![json!({"to": INSTANCE, "data": balance_of(USER)}), "latest"],
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(denial_reason(err), "selector_not_allowed");
+
+    // Deployed contracts are looked up once.
+    assert_eq!(sequencer.requests("eth_getCode").len(), 1);
+    assert_eq!(sequencer.requests("eth_call").len(), 2);
+}
+
+#[tokio::test]
+async fn instance_deployed_by_factory_gets_entry_methods() {
+    let deployed = json!({
+        "address": "0x0000000000000000000000000000000000008006",
+        "topics": [
+            "0x290afdae231a3fc0bbae8b1af63698b0a1d79b21ad17df0342dfb952fe74f8e5",
+            format!("0x{:0>64}", &FACTORY[2..]),
+            format!("0x{}", "11".repeat(32)),
+            format!("0x{:0>64}", &INSTANCE[2..]),
+        ],
+        "data": "0x",
+    });
+    let sequencer = MockSequencer::builder()
+        .respond("eth_getCode", json!(format!("0x{}", "11".repeat(32))))
+        .respond("eth_blockNumber", json!("0x10"))
+        .respond("eth_getLogs", json!([deployed]))
+        .respond("eth_call", json!("0x2a"))
+        .start()
+        .await;
+    let proxy = start_proxy_with(&sequencer, INSTANCES_CONFIG).await;
+
+    let result: Value = client(&proxy, None)
+        .request(
+            "eth_call",
+            rpc_params![json!({"to": INSTANCE, "data": balance_of(USER)}), "latest"],
+        )
+        .await
+        .unwrap();
+    assert_eq!(result, json!("0x2a"));
+
+    let lookups = sequencer.requests("eth_getLogs");
+    assert_eq!(lookups.len(), 1);
+    assert_eq!(
+        lookups[0].params[0]["topics"][3],
+        json!(format!("0x{:0>64}", &INSTANCE[2..]))
+    );
+    // Only the block in which the contract got its code (the mock has it in every block).
+    assert_eq!(lookups[0].params[0]["fromBlock"], json!("0x0"));
+    assert_eq!(lookups[0].params[0]["toBlock"], json!("0x0"));
+}
+
+#[tokio::test]
+async fn instance_logs_are_not_visible() {
+    let sequencer = MockSequencer::builder()
+        .respond(
+            "zks_sendRawTransactionWithDetailedOutput",
+            json!({
+                "transactionHash": format!("0x{:064x}", 1),
+                "storageLogs": [],
+                "events": [{"address": INSTANCE, "topics": [], "data": "0x"}],
+            }),
+        )
+        .start()
+        .await;
+    let proxy = start_proxy_with(&sequencer, INSTANCES_CONFIG).await;
+
+    // Even though every contract deployed by the factory is fully whitelisted.
+    let result: Value = client(&proxy, None)
+        .request(
+            "zks_sendRawTransactionWithDetailedOutput",
+            rpc_params!["0x00"],
+        )
+        .await
+        .unwrap();
+    assert_eq!(result["events"], json!([]));
+    assert!(sequencer.requests("eth_getCode").is_empty());
+}
+
+#[tokio::test]
+async fn failed_instance_lookups_are_cached() {
+    let sequencer = MockSequencer::builder()
+        .fail("eth_getCode", -32000, "header not found")
+        .start()
+        .await;
+    let proxy = start_proxy_with(&sequencer, INSTANCES_CONFIG).await;
+
+    for _ in 0..2 {
+        let err = client(&proxy, None)
+            .request::<Value, _>(
+                "eth_call",
+                rpc_params![json!({"to": INSTANCE, "data": "0x18160ddd"}), "latest"],
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(denial_reason(err), "instance_lookup_failed");
+    }
+    assert_eq!(sequencer.requests("eth_getCode").len(), 1);
+}
+
+#[tokio::test]
+async fn instance_is_not_looked_up_for_unlisted_selectors() {
+    let sequencer = MockSequencer::builder()
+        .respond("eth_getCode", json!(format!("0x{}", "00".repeat(32))))
+        .start()
+        .await;
+    let config = r#"
+allow_contract_creation: false
+verbose_errors: true
+whitelist:
+  - code_hash: "0x01000001f862bd776c8fc18b8e9f8e20089714856ee233b3902a591d0d5f2925"
+    fully_whitelisted: false
+    methods:
+      unrestricted:
+        - "18160ddd"
+"#;
+    let proxy = start_proxy_with(&sequencer, config).await;
+
+    let err = client(&proxy, None)
+        .request::<Value, _>(
+            "eth_call",
+            rpc_params![json!({"to": INSTANCE, "data": balance_of(USER)}), "latest"],
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(denial_reason(err), "contract_not_whitelisted");
+    assert!(sequencer.requests("eth_getCode").is_empty());
+}
+
+const NEW_CONTRACT: &str = "0x6b3e9a1f5c7d2e4a8b0c1d3e5f7a9b2c4d6e8f01";
+
+fn admin_client(proxy: &RunningProxy, token: &str) -> HttpClient {
+    let mut headers = http::HeaderMap::new();
+    headers.insert(
+        http::header::AUTHORIZATION,
+        format!("Bearer {}", token).parse().unwrap(),
+    );
+    HttpClientBuilder::default()
+        .set_headers(headers)
+        .build(format!("http://{}", proxy.admin_addr.unwrap()))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn admin_changes_whitelist_credentials_and_maintenance() {
+    let sequencer = MockSequencer::builder()
+        .respond("eth_call", json!("0x2a"))
+        .start()
+        .await;
+    let config_path =
+        std::env::temp_dir().join(format!("doubleo-admin-{}.yaml", std::process::id()));
+    let config_yaml = format!(
+        "{}admin:\n  address: \"127.0.0.1:0\"\n  token: \"secret\"\n",
+        TEST_CONFIG
+    );
+    std::fs::write(&config_path, &config_yaml).unwrap();
+    let config: Config = serde_yaml::from_str(&config_yaml).unwrap();
+    let proxy = ProxyBuilder::new(sequencer.url.clone(), config)
+        .config_path(config_path.to_str().unwrap())
+        .build()
+        .await
+        .unwrap()
+        .start("127.0.0.1:0")
+        .await
+        .unwrap();
+    let admin = admin_client(&proxy, "secret");
+    let new_call = json!({"to": NEW_CONTRACT, "data": "0x18160ddd"});
+
+    // Wrong token.
+    assert!(admin_client(&proxy, "guess")
+        .request::<Value, _>("admin_stats", rpc_params![])
+        .await
+        .is_err());
+
+    // Rejected without breaking the whitelist for the following changes.
+    let err = admin
+        .request::<bool, _>(
+            "admin_addWhitelistEntry",
+            rpc_params![json!({"address": "0xnot-an-address", "fully_whitelisted": true})],
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(call_error(err).code(), ErrorCode::InvalidParams.code());
+
+    let err = client(&proxy, None)
+        .request::<Value, _>("eth_call", rpc_params![new_call.clone(), "latest"])
+        .await
+        .unwrap_err();
+    assert_eq!(denial_reason(err), "contract_not_whitelisted");
+    let added: bool = admin
+        .request(
+            "admin_addWhitelistEntry",
+            rpc_params![json!({"address": NEW_CONTRACT, "fully_whitelisted": true})],
+        )
+        .await
+        .unwrap();
+    assert!(added);
+    client(&proxy, None)
+        .request::<Value, _>("eth_call", rpc_params![new_call.clone(), "latest"])
+        .await
+        .unwrap();
+    let persisted: Config =
+        serde_yaml::from_str(&std::fs::read_to_string(&config_path).unwrap()).unwrap();
+    assert!(persisted
+        .whitelist
+        .iter()
+        .any(|entry| entry.address.as_deref() == Some(NEW_CONTRACT)));
+
+    add_credential(&proxy, "owner", USER).await;
+    let credentials: Value = admin
+        .request("admin_listCredentials", rpc_params![])
+        .await
+        .unwrap();
+    assert_eq!(credentials[0]["addresses"], json!([USER]));
+    let revoked: bool = admin
+        .request(
+            "admin_revokeCredential",
+            rpc_params![credentials[0]["id"].clone()],
+        )
+        .await
+        .unwrap();
+    assert!(revoked);
+    let err = client(&proxy, Some("owner"))
+        .request::<Value, _>("eth_call", rpc_params![new_call.clone(), "latest"])
+        .await
+        .unwrap_err();
+    assert_eq!(denial_reason(err), "unknown_credential");
+
+    admin
+        .request::<bool, _>("admin_setMaintenance", rpc_params![true])
+        .await
+        .unwrap();
+    let err = client(&proxy, None)
+        .request::<Value, _>("eth_call", rpc_params![new_call, "latest"])
+        .await
+        .unwrap_err();
+    assert_eq!(call_error(err).code(), MAINTENANCE_CODE);
+    let stats: Value = admin.request("admin_stats", rpc_params![]).await.unwrap();
+    assert_eq!(stats["maintenance"], json!(true));
+
+    std::fs::remove_file(config_path).unwrap();
+}
+
+#[tokio::test]
+async fn websocket_calls_count_against_rate_limits() {
+    let sequencer = MockSequencer::builder().start().await;
+    let config = format!(
+        r#"{}
+rate_limit:
+  per_credential:
+    reads:
+      burst: 1
+      per_second: 0.001
+    auth:
+      burst: 2
+      per_second: 0.001
+"#,
+        TEST_CONFIG
+    );
+    let proxy = start_proxy_with(&sequencer, &config).await;
+    // Takes the first auth token of the credential, the handshake takes the second.
+    add_credential(&proxy, "abcd", USER).await;
+    let ws = WsClientBuilder::default()
+        .build(format!("ws://{}/abcd", proxy.local_addr))
+        .await
+        .unwrap();
+
+    let chain_id: String = ws.request("eth_chainId", rpc_params![]).await.unwrap();
+    assert_eq!(chain_id, "0x104");
+    let err = ws
+        .request::<Value, _>("eth_getBalance", rpc_params![USER, "latest"])
+        .await
+        .unwrap_err();
+    assert_eq!(call_error(err).code(), RATE_LIMITED_CODE);
+    let err = ws
+        .request::<bool, _>("privateeth_checkCredential", rpc_params!["guess"])
+        .await
+        .unwrap_err();
+    assert_eq!(call_error(err).code(), RATE_LIMITED_CODE);
+}
+
+#[tokio::test]
+async fn websocket_calls_use_handshake_credential() {
+    let sequencer = MockSequencer::builder()
+        .respond("eth_getBalance", json!("0x2a"))
+        .start()
+        .await;
+    let proxy = start_proxy(&sequencer).await;
+    add_credential(&proxy, "abcd", USER).await;
+    let ws = WsClientBuilder::default()
+        .build(format!("ws://{}/abcd", proxy.local_addr))
+        .await
+        .unwrap();
+
+    // Served as privateeth_getBalance with the credential of the handshake.
+    let balance: String = ws
+        .request("eth_getBalance", rpc_params![USER, "latest"])
+        .await
+        .unwrap();
+    assert_eq!(balance, "0x2a");
+    let err = ws
+        .request::<Value, _>(
+            "privateeth_getBalance",
+            rpc_params!["other", USER, "latest"],
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(denial_reason(err), "unknown_credential");
+
+    // Without a credential, only the public methods.
+    let anonymous = WsClientBuilder::default()
+        .build(format!("ws://{}", proxy.local_addr))
+        .await
+        .unwrap();
+    let err = anonymous
+        .request::<Value, _>("privateeth_getBalance", rpc_params!["abcd", USER, "latest"])
+        .await
+        .unwrap_err();
+    assert_eq!(denial_reason(err), "missing_credential");
+    assert_eq!(sequencer.requests("eth_getBalance").len(), 1);
+}
+
+// Client whose requests claim to be forwarded for `forwarded`.
+fn forwarded_client(proxy: &RunningProxy, forwarded: &str) -> HttpClient {
+    let mut headers = http::HeaderMap::new();
+    headers.insert("x-forwarded-for", forwarded.parse().unwrap());
+    HttpClientBuilder::default()
+        .set_headers(headers)
+        .build(format!("http://{}", proxy.local_addr))
+        .unwrap()
+}
+
+async fn is_rate_limited(client: HttpClient) -> bool {
+    match client
+        .request::<Value, _>("eth_chainId", rpc_params![])
+        .await
+    {
+        Ok(_) => false,
+        Err(err) => call_error(err).code() == RATE_LIMITED_CODE,
+    }
+}
+
+#[tokio::test]
+async fn rate_limits_trust_forwarded_for_only_from_proxies() {
+    let sequencer = MockSequencer::builder().start().await;
+    let limits = "rate_limit:\n  per_ip:\n    reads: { burst: 1, per_second: 0.001 }\n";
+
+    // Without trusted proxies, the header is ignored.
+    let proxy = start_proxy_with(&sequencer, &format!("{}{}", TEST_CONFIG, limits)).await;
+    assert!(!is_rate_limited(forwarded_client(&proxy, "1.1.1.1")).await);
+    assert!(is_rate_limited(forwarded_client(&proxy, "2.2.2.2")).await);
+
+    // Behind a trusted proxy, the client is the address it appended.
+    let config = format!(
+        "{}{}  trusted_proxies: [\"127.0.0.1\"]\n",
+        TEST_CONFIG, limits
+    );
+    let proxy = start_proxy_with(&sequencer, &config).await;
+    assert!(!is_rate_limited(forwarded_client(&proxy, "9.9.9.9, 1.1.1.1")).await);
+    assert!(!is_rate_limited(forwarded_client(&proxy, "9.9.9.9, 2.2.2.2")).await);
+    assert!(is_rate_limited(forwarded_client(&proxy, "8.8.8.8, 1.1.1.1")).await);
+}
+
+#[tokio::test]
+async fn detailed_send_output_hides_storage_and_private_events() {
+    let event = |address: &str| {
+        json!({
+            "address": address,
+            "topics": [],
+            "data": "0x",
+        })
+    };
+    let sequencer = MockSequencer::builder()
+        .respond(
+            "zks_sendRawTransactionWithDetailedOutput",
+            json!({
+                "transactionHash": format!("0x{:064x}", 1),
+                "storageLogs": [{"address": TOKEN_CONTRACT, "key": "0x1", "writtenValue": "0x2"}],
+                "events": [event(PUBLIC_CONTRACT), event(TOKEN_CONTRACT)],
+            }),
+        )
+        .start()
+        .await;
+    let proxy = start_proxy(&sequencer).await;
+
+    let result: Value = client(&proxy, None)
+        .request(
+            "zks_sendRawTransactionWithDetailedOutput",
+            rpc_params!["0x00"],
+        )
+        .await
+        .unwrap();
+    assert_eq!(result["storageLogs"], json!([]));
+    let events = result["events"].as_array().unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0]["address"], json!(PUBLIC_CONTRACT));
+}
+
+#[tokio::test]
+async fn public_filters_evict_least_recently_polled() {
+    let sequencer = MockSequencer::builder()
+        .respond("eth_blockNumber", json!("0x10"))
+        .start()
+        .await;
+    let config = format!(
+        "{}filters:\n  max_filters_per_owner: 1\n  max_public_filters: 2\n",
+        TEST_CONFIG
+    );
+    let proxy = start_proxy_with(&sequencer, &config).await;
+    let public = &client(&proxy, None);
+    let install = || async move {
+        public
+            .request::<Value, _>("eth_newBlockFilter", rpc_params![])
+            .await
+            .unwrap()
+    };
+    let poll = |id: Value| async move {
+        public
+            .request::<Value, _>("eth_getFilterChanges", rpc_params![id])
+            .await
+    };
+
+    let first = install().await;
+    let second = install().await;
+    poll(first.clone()).await.unwrap();
+    // Makes room by removing the second one - the first was polled since.
+    let third = install().await;
+    poll(first).await.unwrap();
+    poll(third).await.unwrap();
+    let err = poll(second).await.unwrap_err();
+    assert_eq!(call_error(err).code(), FILTER_NOT_FOUND_CODE);
+
+    // Credentials have their own budget.
+    add_credential(&proxy, "abcd", USER).await;
+    client(&proxy, Some("abcd"))
+        .request::<Value, _>("eth_newBlockFilter", rpc_params![])
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn log_filters_stop_at_their_range() {
+    let block_hash = format!("0x{:064x}", 0x13);
+    let sequencer = MockSequencer::builder()
+        // Installs at 0x10, then polls at 0x14 and 0x15.
+        .respond_in_order(
+            "eth_blockNumber",
+            vec![
+                json!("0x10"),
+                json!("0x10"),
+                json!("0x14"),
+                json!("0x14"),
+                json!("0x15"),
+            ],
+        )
+        .respond("eth_getLogs", json!([]))
+        .start()
+        .await;
+    let proxy = start_proxy(&sequencer).await;
+    let public = client(&proxy, None);
+
+    let until = public
+        .request::<Value, _>(
+            "eth_newFilter",
+            rpc_params![json!({"address": PUBLIC_CONTRACT, "toBlock": "0x12"})],
+        )
+        .await
+        .unwrap();
+    let by_hash = public
+        .request::<Value, _>(
+            "eth_newFilter",
+            rpc_params![json!({"address": PUBLIC_CONTRACT, "blockHash": block_hash})],
+        )
+        .await
+        .unwrap();
+    for _ in 0..2 {
+        for id in [&until, &by_hash] {
+            public
+                .request::<Value, _>("eth_getFilterChanges", rpc_params![id])
+                .await
+                .unwrap();
+        }
+    }
+
+    // Each one is queried once - up to its 'toBlock', or for its block hash.
+    let queries = sequencer.requests("eth_getLogs");
+    assert_eq!(queries.len(), 2);
+    assert_eq!(queries[0].params[0]["fromBlock"], json!("0x11"));
+    assert_eq!(queries[0].params[0]["toBlock"], json!("0x12"));
+    assert_eq!(queries[1].params[0]["blockHash"], json!(block_hash));
+}
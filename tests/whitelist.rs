@@ -0,0 +1,176 @@
+use std::collections::HashSet;
+
+use proptest::{collection::vec, prelude::*, sample::select};
+use zksync_types::{transaction_request::CallRequest, web3::Bytes, Address};
+
+use doubleo::config::OwnerLookup;
+use doubleo::error::Denial;
+use doubleo::{ContractWhitelist, Methods, WhitelistEntry};
+
+// Small pools, so that contracts, selectors and users collide often.
+const SELECTORS: &[&str] = &["18160ddd", "70a08231", "a9059cbb"];
+
+fn pool_address() -> impl Strategy<Value = Address> {
+    (1..=4u64).prop_map(Address::from_low_u64_be)
+}
+
+fn selectors() -> impl Strategy<Value = Option<Vec<String>>> {
+    proptest::option::of(vec(select(SELECTORS).prop_map(str::to_string), 0..3))
+}
+
+fn entry() -> impl Strategy<Value = WhitelistEntry> {
+    (
+        pool_address(),
+        any::<bool>(),
+        any::<bool>(),
+        selectors(),
+        selectors(),
+    )
+        .prop_map(
+            |(address, fully_whitelisted, has_methods, unrestricted, requires_authorization)| {
+                WhitelistEntry {
+                    address: Some(format!("{:?}", address)),
+                    code_hash: None,
+                    factory: None,
+                    fully_whitelisted,
+                    methods: has_methods.then_some(Methods {
+                        unrestricted,
+                        requires_authorization,
+                        owner_lookup: None,
+                        response_filters: None,
+                    }),
+                    script: None,
+                }
+            },
+        )
+}
+
+fn calldata() -> impl Strategy<Value = Vec<u8>> {
+    prop_oneof![
+        // Garbage, including calldata shorter than a selector.
+        vec(any::<u8>(), 0..8),
+        // Known selector with an address as the first param.
+        (select(SELECTORS), pool_address(), vec(any::<u8>(), 0..32)).prop_map(
+            |(selector, user, rest)| {
+                let mut data = hex::decode(selector).unwrap();
+                data.extend_from_slice(&[0; 12]);
+                data.extend_from_slice(user.as_bytes());
+                data.extend(rest);
+                data
+            }
+        ),
+    ]
+}
+
+fn call_request() -> impl Strategy<Value = CallRequest> {
+    let to = prop_oneof![pool_address(), any::<[u8; 20]>().prop_map(Address::from),];
+    (proptest::option::of(to), proptest::option::of(calldata())).prop_map(|(to, data)| {
+        let mut req = CallRequest::default();
+        req.to = to;
+        req.data = data.map(Bytes);
+        req
+    })
+}
+
+fn users() -> impl Strategy<Value = HashSet<Address>> {
+    proptest::collection::hash_set(pool_address(), 0..3)
+}
+
+// Address in the first param of the calldata (same rule as the whitelist).
+fn first_user(req: &CallRequest) -> Option<Address> {
+    let data = &req.data.as_ref()?.0;
+    if data.len() < 36 || data[4..16].iter().any(|byte| *byte != 0) {
+        return None;
+    }
+    Some(Address::from_slice(&data[16..36]))
+}
+
+proptest! {
+    #[test]
+    fn non_whitelisted_contract_is_denied(
+        entries in vec(entry(), 0..4),
+        req in call_request(),
+        users in users(),
+    ) {
+        let whitelisted = req.to.map_or(false, |to| {
+            entries.iter().any(|entry| entry.address == Some(format!("{:?}", to)))
+        });
+        prop_assume!(!whitelisted);
+        let whitelist = ContractWhitelist::init(entries).unwrap();
+
+        prop_assert!(whitelist.allow_unauthorized_call(&req).is_err());
+        prop_assert!(whitelist.allow_authorized_call(&req, &users).is_err());
+    }
+
+    #[test]
+    fn short_calldata_has_no_selector(
+        entry in entry(),
+        data in vec(any::<u8>(), 0..4),
+        users in users(),
+    ) {
+        prop_assume!(!entry.fully_whitelisted);
+        let to = entry.address.as_ref().unwrap().parse::<Address>().unwrap();
+        let whitelist = ContractWhitelist::init(vec![entry]).unwrap();
+        let mut req = CallRequest::default();
+        req.to = Some(to);
+        req.data = Some(Bytes(data));
+
+        prop_assert_eq!(
+            whitelist.allow_unauthorized_call(&req),
+            Err(Denial::MissingSelector { contract: to })
+        );
+        prop_assert!(whitelist.allow_authorized_call(&req, &users).is_err());
+    }
+
+    #[test]
+    fn credentials_never_narrow_access(
+        entries in vec(entry(), 0..4),
+        req in call_request(),
+        users in users(),
+    ) {
+        let whitelist = ContractWhitelist::init(entries).unwrap();
+        if whitelist.allow_unauthorized_call(&req).is_ok() {
+            prop_assert!(whitelist.allow_authorized_call(&req, &users).is_ok());
+        }
+    }
+
+    #[test]
+    fn unbound_users_get_no_extra_access(
+        entries in vec(entry(), 0..4),
+        req in call_request(),
+        users in users(),
+    ) {
+        prop_assume!(first_user(&req).map_or(true, |user| !users.contains(&user)));
+        let whitelist = ContractWhitelist::init(entries).unwrap();
+
+        prop_assert_eq!(
+            whitelist.allow_authorized_call(&req, &users).is_ok(),
+            whitelist.allow_unauthorized_call(&req).is_ok()
+        );
+    }
+}
+
+#[test]
+fn owner_lookup_rules_are_checked_on_load() {
+    let entry = |selector: &str, arg: usize| WhitelistEntry {
+        address: Some(format!("{:?}", Address::from_low_u64_be(1))),
+        code_hash: None,
+        factory: None,
+        fully_whitelisted: false,
+        methods: Some(Methods {
+            unrestricted: None,
+            requires_authorization: None,
+            owner_lookup: Some(vec![OwnerLookup {
+                selector: selector.to_string(),
+                lookup: "6352211e".to_string(),
+                arg,
+            }]),
+            response_filters: None,
+        }),
+        script: None,
+    };
+
+    assert!(entry("c87b56dd", 1).validate().is_ok());
+    assert!(entry("c87b56", 1).validate().is_err());
+    assert!(entry("c87b56dd", usize::MAX / 16).validate().is_err());
+}